@@ -1,5 +1,13 @@
 import axios, { AxiosInstance } from "axios";
-import { ApiToken, Playlist, PlaylistElement, Song } from "./types";
+import {
+  ApiToken,
+  Playlist,
+  PlaylistElement,
+  PlaylistFolder,
+  PlaylistSummary,
+  PlaylistTree,
+  Song,
+} from "./types";
 
 export type ApiError = {
   message: string;
@@ -41,11 +49,21 @@ export default class ApiClient {
     });
   }
 
-  async getPlaylists() {
-    const { data } = await this.axiosClient.get<Playlist[]>("/playlists");
+  async getPlaylistTree() {
+    const { data } = await this.axiosClient.get<PlaylistTree>("/playlists/tree");
     return data;
   }
 
+  // Every playlist in the tree, with the ones in folders after the top level ones
+  async getPlaylists() {
+    const tree = await this.getPlaylistTree();
+
+    const flatten = (folders: PlaylistFolder[]): PlaylistSummary[] =>
+      folders.flatMap((f) => [...f.playlists, ...flatten(f.folders)]);
+
+    return [...tree.playlists, ...flatten(tree.folders)];
+  }
+
   // TODO: Make this take a playlist
   async createPlaylist(name: string) {
     const { data } = await this.axiosClient.post<Playlist>("/playlists", {
@@ -3,11 +3,11 @@ import { Plus, X } from "@phosphor-icons/react";
 import { useState } from "react";
 import { NavLink } from "react-router-dom";
 import usePlaylists from "../hooks/usePlaylists";
-import type { Playlist as PlaylistT } from "../types";
+import type { PlaylistSummary } from "../types";
 import { useAuth } from "../contexts/auth";
 import usePlaylist from "../hooks/usePlaylist";
 
-function Playlist({ playlist }: { playlist: PlaylistT }) {
+function Playlist({ playlist }: { playlist: PlaylistSummary }) {
   const { deletePlaylistMutation } = usePlaylist(playlist.id);
 
   // TODO: If active and delete is pressed, redirect to /playlists
@@ -50,7 +50,7 @@ function Playlist({ playlist }: { playlist: PlaylistT }) {
 export default function PlaylistSelector({
   playlists,
 }: {
-  playlists: PlaylistT[];
+  playlists: PlaylistSummary[];
 }) {
   const [searchFilter, setSearchFilter] = useState("");
   const [addName, setAddName] = useState("");
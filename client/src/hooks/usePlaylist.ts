@@ -41,7 +41,7 @@ export default function usePlaylist(id: number) {
   );
 
   const deletePlaylistMutation = useMutation(
-    (playlist: Playlist) => {
+    (playlist: Pick<Playlist, "id">) => {
       if (!apiClient) {
         return Promise.reject();
       } else {
@@ -1,6 +1,6 @@
 import { useMutation, useQuery, useQueryClient } from "react-query";
 import { useAuth } from "../contexts/auth";
-import { Playlist } from "../types";
+import { PlaylistSummary } from "../types";
 
 export default function usePlaylists() {
   const queryClient = useQueryClient();
@@ -12,7 +12,7 @@ export default function usePlaylists() {
     data: playlists,
   } = useQuery(
     "playlists",
-    async (): Promise<Playlist[]> => {
+    async (): Promise<PlaylistSummary[]> => {
       if (!apiClient) {
         throw new Error("no api client");
       } else {
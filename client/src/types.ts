@@ -20,6 +20,35 @@ export type Playlist = {
   id: number;
 };
 
+export type PlaylistPlacement = {
+  folder_id: number | null;
+  position: number;
+  pinned: boolean;
+  archived: boolean;
+};
+
+export type PlaylistSummary = PlaylistPlacement & {
+  id: number;
+  name: string;
+  element_count: number;
+  duration_ms: number;
+  cover_image_urls: string[];
+};
+
+export type PlaylistFolder = {
+  id: number;
+  parent_id: number | null;
+  name: string;
+  position: number;
+  folders: PlaylistFolder[];
+  playlists: PlaylistSummary[];
+};
+
+export type PlaylistTree = {
+  folders: PlaylistFolder[];
+  playlists: PlaylistSummary[];
+};
+
 export enum DraggableType {
   Element,
   Song,
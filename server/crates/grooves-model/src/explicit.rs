@@ -0,0 +1,83 @@
+use serde::{Deserialize, Serialize};
+
+use crate::{PlaylistElement, Song};
+
+/// What to do with songs spotify marks as explicit. Users have a default, which a play can
+/// override
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash, Serialize, Deserialize, sqlx::Type)]
+#[serde(rename_all = "snake_case")]
+#[sqlx(type_name = "explicit_filter", rename_all = "snake_case")]
+pub enum ExplicitFilter {
+    /// Play everything
+    #[default]
+    Allow,
+    /// Leave explicit songs out of the elements they're in
+    SkipTracks,
+    /// Also leave out elements where most of the songs are explicit
+    SkipElements,
+}
+
+impl ExplicitFilter {
+    pub fn allows_song(self, song: &Song) -> bool {
+        song.is_playable() && (self == Self::Allow || !song.explicit)
+    }
+
+    /// The index of the song the element starts playing from
+    pub fn first_song(self, element: &PlaylistElement) -> Option<usize> {
+        element.songs.iter().position(|s| self.allows_song(s))
+    }
+
+    /// Whether the element is played at all. Elements without playable songs never are, since
+    /// spotify can make every song of an album unavailable without the user doing anything
+    pub fn allows_element(self, element: &PlaylistElement) -> bool {
+        let playable = element.songs.iter().filter(|s| s.is_playable()).count();
+        let explicit = element
+            .songs
+            .iter()
+            .filter(|s| s.is_playable() && s.explicit)
+            .count();
+
+        match self {
+            _ if playable == 0 => false,
+            Self::Allow => true,
+            Self::SkipTracks => explicit < playable,
+            Self::SkipElements => explicit * 2 <= playable,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn element(explicit: &[bool]) -> PlaylistElement {
+        PlaylistElement {
+            songs: explicit
+                .iter()
+                .map(|&explicit| Song {
+                    explicit,
+                    ..Song::test("6rqhFgbbKwnb9MLmUQDhG6")
+                })
+                .collect(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn explicit_songs_and_mostly_explicit_elements_are_skipped() {
+        let mixed = element(&[true, false, true]);
+        let half = element(&[true, false]);
+        let all = element(&[true, true]);
+
+        assert_eq!(ExplicitFilter::Allow.first_song(&mixed), Some(0));
+        assert_eq!(ExplicitFilter::SkipTracks.first_song(&mixed), Some(1));
+        assert_eq!(ExplicitFilter::SkipTracks.first_song(&all), None);
+
+        assert!(ExplicitFilter::SkipTracks.allows_element(&mixed));
+        assert!(!ExplicitFilter::SkipTracks.allows_element(&all));
+
+        assert!(!ExplicitFilter::SkipElements.allows_element(&mixed));
+        assert!(ExplicitFilter::SkipElements.allows_element(&half));
+        assert!(!ExplicitFilter::Allow.allows_element(&element(&[])));
+    }
+}
@@ -0,0 +1,164 @@
+use std::collections::{HashMap, HashSet};
+
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+
+use crate::PlaylistSummary;
+
+/// A user's own folder for organizing the playlists they have access to. Folders can be
+/// inside other folders
+#[derive(Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize, FromRow)]
+pub struct PlaylistFolder {
+    pub id: i32,
+    pub user_id: i32,
+    /// None for folders at the top level
+    pub parent_id: Option<i32>,
+    pub name: String,
+    /// Folders are listed by position, then by id
+    pub position: i32,
+}
+
+/// Where a playlist is in a member's list. Every member places playlists separately
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash, Serialize, Deserialize, FromRow)]
+pub struct PlaylistPlacement {
+    /// None for playlists at the top level
+    pub folder_id: Option<i32>,
+    /// Playlists are listed pinned first, then by position, then by id
+    pub position: i32,
+    pub pinned: bool,
+    /// Archived playlists are left out of the list unless they're asked for
+    pub archived: bool,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PlaylistTree {
+    pub folders: Vec<FolderNode>,
+    pub playlists: Vec<PlaylistSummary>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FolderNode {
+    #[serde(flatten)]
+    pub folder: PlaylistFolder,
+    pub folders: Vec<FolderNode>,
+    pub playlists: Vec<PlaylistSummary>,
+}
+
+impl PlaylistTree {
+    /// Puts the playlists in their folders. Anything in a folder that isn't given ends up at
+    /// the top level
+    pub fn new(folders: Vec<PlaylistFolder>, playlists: Vec<PlaylistSummary>) -> Self {
+        let folder_ids: HashSet<i32> = folders.iter().map(|f| f.id).collect();
+        let known = |id: Option<i32>| id.filter(|id| folder_ids.contains(id));
+
+        let mut child_folders: HashMap<Option<i32>, Vec<PlaylistFolder>> = HashMap::new();
+        for folder in folders {
+            child_folders
+                .entry(known(folder.parent_id))
+                .or_default()
+                .push(folder);
+        }
+
+        let mut child_playlists: HashMap<Option<i32>, Vec<PlaylistSummary>> = HashMap::new();
+        for entry in playlists {
+            child_playlists
+                .entry(known(entry.placement.folder_id))
+                .or_default()
+                .push(entry);
+        }
+
+        let (folders, playlists) = children(None, &mut child_folders, &mut child_playlists);
+        Self { folders, playlists }
+    }
+}
+
+fn children(
+    parent_id: Option<i32>,
+    child_folders: &mut HashMap<Option<i32>, Vec<PlaylistFolder>>,
+    child_playlists: &mut HashMap<Option<i32>, Vec<PlaylistSummary>>,
+) -> (Vec<FolderNode>, Vec<PlaylistSummary>) {
+    let mut folders = child_folders.remove(&parent_id).unwrap_or_default();
+    folders.sort_by_key(|f| (f.position, f.id));
+
+    let mut playlists = child_playlists.remove(&parent_id).unwrap_or_default();
+    playlists.sort_by_key(|e| (!e.placement.pinned, e.placement.position, e.id));
+
+    let folders = folders
+        .into_iter()
+        .map(|folder| {
+            let (folders, playlists) = children(Some(folder.id), child_folders, child_playlists);
+            FolderNode {
+                folder,
+                folders,
+                playlists,
+            }
+        })
+        .collect();
+
+    (folders, playlists)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn folder(id: i32, parent_id: Option<i32>) -> PlaylistFolder {
+        PlaylistFolder {
+            id,
+            user_id: 1,
+            parent_id,
+            name: String::new(),
+            position: 0,
+        }
+    }
+
+    fn summary(id: i32, folder_id: Option<i32>, position: i32, pinned: bool) -> PlaylistSummary {
+        PlaylistSummary {
+            id,
+            name: String::new(),
+            owner_id: 1,
+            version: 0,
+            element_count: 0,
+            duration_ms: 0,
+            cover_image_urls: vec![],
+            placement: PlaylistPlacement {
+                folder_id,
+                position,
+                pinned,
+                archived: false,
+            },
+        }
+    }
+
+    fn ids(playlists: &[PlaylistSummary]) -> Vec<i32> {
+        playlists.iter().map(|e| e.id).collect()
+    }
+
+    #[test]
+    fn playlists_go_in_nested_folders() {
+        let tree = PlaylistTree::new(
+            vec![folder(1, None), folder(2, Some(1))],
+            vec![summary(1, None, 0, false), summary(2, Some(2), 0, false)],
+        );
+
+        assert_eq!(ids(&tree.playlists), vec![1]);
+        assert_eq!(tree.folders.len(), 1);
+        assert!(tree.folders[0].playlists.is_empty());
+        assert_eq!(ids(&tree.folders[0].folders[0].playlists), vec![2]);
+    }
+
+    #[test]
+    fn pinned_playlists_come_first() {
+        let tree = PlaylistTree::new(
+            vec![],
+            vec![
+                summary(1, None, 0, false),
+                summary(2, None, 2, true),
+                summary(3, None, 1, true),
+                summary(4, Some(7), 0, false),
+            ],
+        );
+
+        assert_eq!(ids(&tree.playlists), vec![3, 2, 1, 4]);
+    }
+}
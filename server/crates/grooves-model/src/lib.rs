@@ -1,7 +1,21 @@
+mod explicit;
+mod folder;
+mod member;
 mod playlist;
+mod resume_point;
+mod revision;
 mod session;
+mod share;
+mod smart;
 mod user;
 
+pub use explicit::*;
+pub use folder::*;
+pub use member::*;
 pub use playlist::*;
+pub use resume_point::*;
+pub use revision::*;
 pub use session::*;
+pub use share::*;
+pub use smart::*;
 pub use user::*;
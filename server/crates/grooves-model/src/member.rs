@@ -0,0 +1,36 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+
+/// What a user can do with a playlist. Each role can do everything the ones before it can
+#[derive(
+    Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize, sqlx::Type,
+)]
+#[serde(rename_all = "snake_case")]
+#[sqlx(type_name = "playlist_role", rename_all = "snake_case")]
+pub enum PlaylistRole {
+    /// Can view and play the playlist
+    Viewer,
+    /// Can also change the playlist's name and elements
+    Editor,
+    /// Can also delete the playlist and manage who has access to it
+    Owner,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize, FromRow)]
+pub struct PlaylistMember {
+    pub playlist_id: i32,
+    pub user_id: i32,
+    pub spotify_id: String,
+    pub role: PlaylistRole,
+}
+
+/// Anyone who accepts the invite becomes a member with its role
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize, FromRow)]
+pub struct PlaylistInvite {
+    pub id: i32,
+    pub playlist_id: i32,
+    pub token: String,
+    pub role: PlaylistRole,
+    pub created_at: DateTime<Utc>,
+}
@@ -1,22 +1,92 @@
-use rspotify::model::TrackId;
+use std::collections::HashSet;
+
+use rspotify::model::{AlbumId, ArtistId, FullAlbum, IdError, Image, TrackId};
 use serde::{Deserialize, Serialize};
 use sqlx::FromRow;
 
+use crate::PlaylistPlacement;
+
+/// Playlists that only exist in a player use this id. Ids from the database start at 1
+pub const EPHEMERAL_PLAYLIST_ID: i32 = 0;
+
 #[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize, FromRow)]
 pub struct Playlist {
     pub id: i32,
     pub name: String,
     pub owner_id: i32,
-    #[sqlx(json)]
+    /// Increases every time the playlist changes
+    pub version: i32,
+    /// Stored in the playlist_element table, so it has to be loaded separately
+    #[sqlx(skip)]
     pub elements: Vec<PlaylistElement>,
 }
 
-#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+impl Playlist {
+    /// A playlist that isn't saved to the database, e.g. for playing search results
+    pub fn ephemeral(owner_id: i32, elements: Vec<PlaylistElement>) -> Self {
+        Self {
+            id: EPHEMERAL_PLAYLIST_ID,
+            name: String::new(),
+            owner_id,
+            version: 0,
+            elements,
+        }
+    }
+
+    pub fn is_ephemeral(&self) -> bool {
+        self.id == EPHEMERAL_PLAYLIST_ID
+    }
+}
+
+/// What playlist lists show instead of the elements, which are only in single playlists
+#[derive(Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize, FromRow)]
+pub struct PlaylistSummary {
+    pub id: i32,
+    pub name: String,
+    pub owner_id: i32,
+    pub version: i32,
+    pub element_count: i32,
+    /// The total of the elements' durations. Elements without one count as 0
+    pub duration_ms: i64,
+    /// The images of the first few elements
+    pub cover_image_urls: Vec<String>,
+    #[serde(flatten)]
+    #[sqlx(flatten)]
+    pub placement: PlaylistPlacement,
+}
+
+/// The metadata fields are missing from elements saved before they were added, until the
+/// backfill fills them in
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
 pub struct PlaylistElement {
     pub name: String,
     pub image_url: String,
     pub artists: String,
     pub songs: Vec<Song>,
+    /// The album the element was made from
+    #[serde(default)]
+    pub album_id: Option<AlbumId<'static>>,
+    /// "YYYY", "YYYY-MM" or "YYYY-MM-DD" depending on how precisely spotify knows it
+    #[serde(default)]
+    pub release_date: Option<String>,
+    #[serde(default)]
+    pub label: Option<String>,
+    #[serde(default)]
+    pub genres: Vec<String>,
+    /// The total duration of the songs
+    #[serde(default)]
+    pub duration_ms: Option<i32>,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    #[serde(default)]
+    pub note: Option<String>,
+    /// From 1 to 5 stars
+    #[serde(default)]
+    pub rating: Option<i32>,
+    /// How likely the element is to be shuffled towards the front compared to the others.
+    /// None is the same as 1
+    #[serde(default)]
+    pub shuffle_weight: Option<i32>,
 }
 
 #[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
@@ -25,4 +95,337 @@ pub struct Song {
     pub image_url: String,
     pub artists: String,
     pub spotify_id: TrackId<'static>,
+    #[serde(default)]
+    pub duration_ms: Option<i32>,
+    #[serde(default)]
+    pub track_number: Option<i32>,
+    #[serde(default)]
+    pub disc_number: Option<i32>,
+    #[serde(default)]
+    pub explicit: bool,
+    #[serde(default)]
+    pub isrc: Option<String>,
+    #[serde(default)]
+    pub artist_ids: Vec<ArtistId<'static>>,
+    /// Set when spotify stops being able to play the song, e.g. because it was removed
+    #[serde(default)]
+    pub unavailable: bool,
+    /// Left out when the element is played, e.g. for skits or bonus tracks
+    #[serde(default)]
+    pub excluded: bool,
+}
+
+impl Song {
+    pub fn is_playable(&self) -> bool {
+        !self.unavailable && !self.excluded
+    }
+
+    /// A playable song named after its track id, with nothing else set
+    #[cfg(any(test, feature = "test-util"))]
+    pub fn test(id: &str) -> Self {
+        Self {
+            name: id.to_owned(),
+            image_url: String::new(),
+            artists: String::new(),
+            spotify_id: TrackId::from_id(id.to_owned()).unwrap(),
+            duration_ms: None,
+            track_number: None,
+            disc_number: None,
+            explicit: false,
+            isrc: None,
+            artist_ids: Vec::new(),
+            unavailable: false,
+            excluded: false,
+        }
+    }
+}
+
+impl PlaylistElement {
+    pub fn shuffle_weight(&self) -> u32 {
+        self.shuffle_weight
+            .and_then(|w| u32::try_from(w).ok())
+            .unwrap_or(1)
+    }
+}
+
+/// Every element of the lists in order, leaving out albums that are already in an earlier
+/// element. Elements that aren't made from an album are all kept
+pub fn merge_elements(lists: Vec<Vec<PlaylistElement>>) -> Vec<PlaylistElement> {
+    let mut seen = HashSet::new();
+
+    lists
+        .into_iter()
+        .flatten()
+        .filter(|e| e.album_id.as_ref().is_none_or(|id| seen.insert(id.clone())))
+        .collect()
+}
+
+/// The elements of `first` whose albums are in every one of `others`
+pub fn intersect_elements(
+    first: Vec<PlaylistElement>,
+    others: &[Vec<PlaylistElement>],
+) -> Vec<PlaylistElement> {
+    let others: Vec<HashSet<&AlbumId>> = others.iter().map(|l| album_ids(l)).collect();
+
+    first
+        .into_iter()
+        .filter(|e| {
+            e.album_id
+                .as_ref()
+                .is_some_and(|id| others.iter().all(|o| o.contains(id)))
+        })
+        .collect()
+}
+
+/// The elements of `first` whose albums aren't in any of `others`
+pub fn subtract_elements(
+    first: Vec<PlaylistElement>,
+    others: &[Vec<PlaylistElement>],
+) -> Vec<PlaylistElement> {
+    let others: HashSet<&AlbumId> = others.iter().flat_map(|l| album_ids(l)).collect();
+
+    first
+        .into_iter()
+        .filter(|e| e.album_id.as_ref().is_none_or(|id| !others.contains(id)))
+        .collect()
+}
+
+fn album_ids(elements: &[PlaylistElement]) -> HashSet<&AlbumId<'static>> {
+    elements
+        .iter()
+        .filter_map(|e| e.album_id.as_ref())
+        .collect()
+}
+
+/// A row of the playlist_element table
+#[derive(Clone, Debug, PartialEq, Eq, FromRow)]
+pub struct PlaylistElementRow {
+    pub id: i32,
+    pub playlist_id: i32,
+    pub position: i32,
+    pub name: String,
+    pub image_url: String,
+    pub artists: String,
+    pub album_id: Option<String>,
+    pub release_date: Option<String>,
+    pub label: Option<String>,
+    #[sqlx(json)]
+    pub genres: Vec<String>,
+    pub duration_ms: Option<i32>,
+    #[sqlx(json)]
+    pub tags: Vec<String>,
+    pub note: Option<String>,
+    pub rating: Option<i32>,
+    pub shuffle_weight: Option<i32>,
+}
+
+impl PlaylistElementRow {
+    pub fn into_element(self, songs: Vec<Song>) -> Result<PlaylistElement, IdError> {
+        Ok(PlaylistElement {
+            name: self.name,
+            image_url: self.image_url,
+            artists: self.artists,
+            songs,
+            album_id: self.album_id.map(AlbumId::from_id).transpose()?,
+            release_date: self.release_date,
+            label: self.label,
+            genres: self.genres,
+            duration_ms: self.duration_ms,
+            tags: self.tags,
+            note: self.note,
+            rating: self.rating,
+            shuffle_weight: self.shuffle_weight,
+        })
+    }
+}
+
+/// A row of the playlist_element_track table
+#[derive(Clone, Debug, PartialEq, Eq, FromRow)]
+pub struct SongRow {
+    pub element_id: i32,
+    pub position: i32,
+    pub name: String,
+    pub image_url: String,
+    pub artists: String,
+    pub spotify_id: String,
+    pub duration_ms: Option<i32>,
+    pub track_number: Option<i32>,
+    pub disc_number: Option<i32>,
+    pub explicit: bool,
+    pub isrc: Option<String>,
+    #[sqlx(json)]
+    pub artist_ids: Vec<String>,
+    pub unavailable: bool,
+    pub excluded: bool,
+}
+
+impl TryFrom<SongRow> for Song {
+    type Error = IdError;
+
+    fn try_from(row: SongRow) -> Result<Self, Self::Error> {
+        Ok(Self {
+            name: row.name,
+            image_url: row.image_url,
+            artists: row.artists,
+            spotify_id: TrackId::from_id(row.spotify_id)?,
+            duration_ms: row.duration_ms,
+            track_number: row.track_number,
+            disc_number: row.disc_number,
+            explicit: row.explicit,
+            isrc: row.isrc,
+            artist_ids: row
+                .artist_ids
+                .into_iter()
+                .map(ArtistId::from_id)
+                .collect::<Result<_, _>>()?,
+            unavailable: row.unavailable,
+            excluded: row.excluded,
+        })
+    }
+}
+
+impl From<FullAlbum> for PlaylistElement {
+    fn from(album: FullAlbum) -> Self {
+        let image_url = get_max_image_url(&album.images).unwrap_or("");
+        let artists = album
+            .artists
+            .iter()
+            .map(|a| &*a.name)
+            .collect::<Vec<_>>()
+            .join(", ");
+
+        let songs: Vec<Song> = album
+            .tracks
+            .items
+            .iter()
+            .filter_map(|s| {
+                Some(Song {
+                    name: s.name.clone(),
+                    image_url: image_url.to_owned(),
+                    artists: artists.clone(),
+                    spotify_id: s.id.clone()?,
+                    duration_ms: Some(s.duration.num_milliseconds() as i32),
+                    track_number: Some(s.track_number as i32),
+                    disc_number: Some(s.disc_number),
+                    explicit: s.explicit,
+                    // Only full tracks have external ids, so this has to be looked up separately
+                    isrc: None,
+                    artist_ids: s.artists.iter().filter_map(|a| a.id.clone()).collect(),
+                    unavailable: false,
+                    excluded: false,
+                })
+            })
+            .collect();
+
+        Self {
+            name: album.name,
+            artists,
+            image_url: image_url.to_owned(),
+            duration_ms: Some(songs.iter().filter_map(|s| s.duration_ms).sum()),
+            songs,
+            album_id: Some(album.id),
+            release_date: Some(album.release_date),
+            label: album.label,
+            genres: album.genres,
+            ..Default::default()
+        }
+    }
+}
+
+fn get_max_image_url(images: &[Image]) -> Option<&str> {
+    images
+        .iter()
+        .max_by_key(|a| a.height.unwrap_or(0))
+        .map(|img| &*img.url)
+}
+
+/// The parts of an element that are up to its playlist's editors rather than spotify
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ElementAnnotations {
+    #[serde(default)]
+    pub tags: Vec<String>,
+    pub note: Option<String>,
+    pub rating: Option<i32>,
+    pub shuffle_weight: Option<i32>,
+}
+
+/// Which elements to show or play. Every condition that's set has to match
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ElementFilter {
+    /// Elements with this tag, ignoring case
+    pub tag: Option<String>,
+    pub min_rating: Option<i32>,
+}
+
+impl ElementFilter {
+    pub fn is_empty(&self) -> bool {
+        self == &Self::default()
+    }
+
+    pub fn matches(&self, element: &PlaylistElement) -> bool {
+        let tag_matches = self
+            .tag
+            .as_ref()
+            .is_none_or(|tag| element.tags.iter().any(|t| t.eq_ignore_ascii_case(tag)));
+
+        let rating_matches = self
+            .min_rating
+            .is_none_or(|min| element.rating.is_some_and(|r| r >= min));
+
+        tag_matches && rating_matches
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// An element made from the album with this id, or from no album if it's None
+    fn element(album_id: Option<&str>) -> PlaylistElement {
+        PlaylistElement {
+            album_id: album_id.map(|id| AlbumId::from_id(id.to_owned()).unwrap()),
+            ..Default::default()
+        }
+    }
+
+    const A: &str = "4LH4d3cOWNNsVw41Gqt2kv";
+    const B: &str = "1weenld61qoidwYuZ1GESA";
+    const C: &str = "2noRn2Aes5aoNVsU6iWThc";
+
+    #[test]
+    fn merge_keeps_the_first_of_each_album() {
+        let merged = merge_elements(vec![
+            vec![element(Some(A)), element(None), element(Some(B))],
+            vec![element(Some(B)), element(None), element(Some(C))],
+        ]);
+
+        assert_eq!(
+            merged,
+            vec![
+                element(Some(A)),
+                element(None),
+                element(Some(B)),
+                element(None),
+                element(Some(C)),
+            ]
+        );
+    }
+
+    #[test]
+    fn intersection_and_difference_compare_albums() {
+        let first = vec![element(Some(A)), element(Some(B)), element(None)];
+        let others = [
+            vec![element(Some(B)), element(Some(C))],
+            vec![element(Some(B)), element(None)],
+        ];
+
+        assert_eq!(
+            intersect_elements(first.clone(), &others),
+            vec![element(Some(B))]
+        );
+        assert_eq!(
+            subtract_elements(first, &others),
+            vec![element(Some(A)), element(None)]
+        );
+    }
 }
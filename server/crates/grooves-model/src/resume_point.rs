@@ -0,0 +1,18 @@
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+
+/// Where a user left off in a playlist
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize, FromRow)]
+pub struct ResumePoint {
+    pub user_id: i32,
+    pub playlist_id: i32,
+    /// Indices into the playlist's elements in the order they're played
+    #[sqlx(json)]
+    pub element_order: Vec<usize>,
+    /// An index into element_order
+    pub current_element: i32,
+    /// An index into the current element's songs
+    pub current_song: i32,
+    /// The seed element_order was shuffled with, if it's known
+    pub seed: Option<i64>,
+}
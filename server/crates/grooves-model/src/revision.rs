@@ -0,0 +1,120 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+
+use crate::PlaylistElement;
+
+/// A snapshot of a playlist, taken every time it's saved
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize, FromRow)]
+pub struct PlaylistRevision {
+    pub id: i32,
+    pub playlist_id: i32,
+    pub author_id: i32,
+    pub name: String,
+    #[sqlx(json)]
+    pub elements: Vec<PlaylistElement>,
+    pub created_at: DateTime<Utc>,
+}
+
+/// A revision without its elements, for listing
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize, FromRow)]
+pub struct PlaylistRevisionSummary {
+    pub id: i32,
+    pub playlist_id: i32,
+    pub author_id: i32,
+    pub name: String,
+    pub element_count: i32,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ElementChange {
+    /// An element at this position in the newer elements that isn't in the older ones
+    Added {
+        position: usize,
+        element: PlaylistElement,
+    },
+    /// An element at this position in the older elements that isn't in the newer ones
+    Removed {
+        position: usize,
+        element: PlaylistElement,
+    },
+}
+
+/// The changes needed to turn `from` into `to`, keeping as many elements in place as possible.
+/// A moved element shows up as being removed and added
+pub fn diff_elements(from: &[PlaylistElement], to: &[PlaylistElement]) -> Vec<ElementChange> {
+    // lcs[i][j] is the length of the longest common subsequence of from[i..] and to[j..]
+    let mut lcs = vec![vec![0usize; to.len() + 1]; from.len() + 1];
+    for i in (0..from.len()).rev() {
+        for j in (0..to.len()).rev() {
+            lcs[i][j] = if from[i] == to[j] {
+                lcs[i + 1][j + 1] + 1
+            } else {
+                lcs[i + 1][j].max(lcs[i][j + 1])
+            };
+        }
+    }
+
+    let mut changes = Vec::new();
+    let (mut i, mut j) = (0, 0);
+    while i < from.len() || j < to.len() {
+        if i < from.len() && j < to.len() && from[i] == to[j] {
+            i += 1;
+            j += 1;
+        } else if j < to.len() && (i == from.len() || lcs[i][j + 1] >= lcs[i + 1][j]) {
+            changes.push(ElementChange::Added {
+                position: j,
+                element: to[j].clone(),
+            });
+            j += 1;
+        } else {
+            changes.push(ElementChange::Removed {
+                position: i,
+                element: from[i].clone(),
+            });
+            i += 1;
+        }
+    }
+
+    changes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn element(name: &str) -> PlaylistElement {
+        PlaylistElement {
+            name: name.to_owned(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn diff_finds_added_and_removed_elements() {
+        let from = [element("a"), element("b"), element("c")];
+        let to = [element("a"), element("c"), element("d")];
+
+        assert_eq!(
+            diff_elements(&from, &to),
+            vec![
+                ElementChange::Removed {
+                    position: 1,
+                    element: element("b"),
+                },
+                ElementChange::Added {
+                    position: 2,
+                    element: element("d"),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn diff_of_identical_elements_is_empty() {
+        let elements = [element("a"), element("b")];
+        assert!(diff_elements(&elements, &elements).is_empty());
+    }
+}
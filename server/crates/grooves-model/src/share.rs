@@ -0,0 +1,12 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+
+/// Anyone with the token can view the playlist, until the share is deleted
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize, FromRow)]
+pub struct PlaylistShare {
+    pub id: i32,
+    pub playlist_id: i32,
+    pub token: String,
+    pub created_at: DateTime<Utc>,
+}
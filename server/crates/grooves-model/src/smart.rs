@@ -0,0 +1,169 @@
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+
+use crate::PlaylistElement;
+
+/// A playlist whose elements come from rules instead of being added by hand. They're worked
+/// out again every time it's played
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize, FromRow)]
+pub struct SmartPlaylist {
+    pub playlist_id: i32,
+    #[sqlx(json)]
+    pub rules: SmartRules,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SmartRules {
+    pub source: SmartSource,
+    /// Every rule has to match for an element to be included
+    #[serde(default)]
+    pub rules: Vec<SmartRule>,
+    /// The most elements to include, taken in the order the source gives them
+    #[serde(default)]
+    pub limit: Option<usize>,
+}
+
+/// Where the elements a smart playlist picks from come from. The same album only shows up once
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum SmartSource {
+    /// Elements of these playlists, or of every playlist the user is a member of if it's empty
+    Playlists {
+        #[serde(default)]
+        playlist_ids: Vec<i32>,
+    },
+    /// Albums saved to the user's spotify library. These don't have tags or ratings
+    Library,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum SmartRule {
+    /// Elements with this tag, ignoring case
+    Tag {
+        tag: String,
+    },
+    /// Elements with this genre, ignoring case
+    Genre {
+        genre: String,
+    },
+    /// Elements where one of the artists' names contains this, ignoring case
+    Artist {
+        name: String,
+    },
+    Label {
+        label: String,
+    },
+    /// Elements released before the start of this year
+    ReleasedBefore {
+        year: i32,
+    },
+    /// Elements released after the end of this year
+    ReleasedAfter {
+        year: i32,
+    },
+    MinRating {
+        rating: i32,
+    },
+    /// Elements the user hasn't played for at least this many days, including ones they've
+    /// never played
+    NotPlayedFor {
+        days: i64,
+    },
+}
+
+impl SmartRules {
+    /// Whether an element played by the user at `last_played` belongs in the playlist
+    pub fn matches(
+        &self,
+        element: &PlaylistElement,
+        last_played: Option<DateTime<Utc>>,
+        now: DateTime<Utc>,
+    ) -> bool {
+        self.rules
+            .iter()
+            .all(|rule| rule.matches(element, last_played, now))
+    }
+
+    pub fn needs_play_history(&self) -> bool {
+        self.rules
+            .iter()
+            .any(|rule| matches!(rule, SmartRule::NotPlayedFor { .. }))
+    }
+}
+
+impl SmartRule {
+    pub fn matches(
+        &self,
+        element: &PlaylistElement,
+        last_played: Option<DateTime<Utc>>,
+        now: DateTime<Utc>,
+    ) -> bool {
+        match self {
+            Self::Tag { tag } => element.tags.iter().any(|t| t.eq_ignore_ascii_case(tag)),
+            Self::Genre { genre } => element.genres.iter().any(|g| g.eq_ignore_ascii_case(genre)),
+            Self::Artist { name } => element
+                .artists
+                .to_lowercase()
+                .contains(&name.to_lowercase()),
+            Self::Label { label } => element
+                .label
+                .as_ref()
+                .is_some_and(|l| l.eq_ignore_ascii_case(label)),
+            Self::ReleasedBefore { year } => release_year(element).is_some_and(|y| y < *year),
+            Self::ReleasedAfter { year } => release_year(element).is_some_and(|y| y > *year),
+            Self::MinRating { rating } => element.rating.is_some_and(|r| r >= *rating),
+            Self::NotPlayedFor { days } => {
+                last_played.is_none_or(|played_at| now - played_at >= Duration::days(*days))
+            }
+        }
+    }
+}
+
+fn release_year(element: &PlaylistElement) -> Option<i32> {
+    element.release_date.as_ref()?.get(..4)?.parse().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn element(release_date: &str, tags: &[&str]) -> PlaylistElement {
+        PlaylistElement {
+            release_date: Some(release_date.to_owned()),
+            tags: tags.iter().map(|t| t.to_string()).collect(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn every_rule_has_to_match() {
+        let rules = SmartRules {
+            source: SmartSource::Library,
+            rules: vec![
+                SmartRule::Tag {
+                    tag: "jazz".to_owned(),
+                },
+                SmartRule::ReleasedBefore { year: 1970 },
+            ],
+            limit: None,
+        };
+        let now = Utc::now();
+
+        assert!(rules.matches(&element("1959-08-17", &["Jazz"]), None, now));
+        assert!(!rules.matches(&element("1970", &["jazz"]), None, now));
+        assert!(!rules.matches(&element("1959", &["rock"]), None, now));
+    }
+
+    #[test]
+    fn not_played_for_includes_unplayed_elements() {
+        let rule = SmartRule::NotPlayedFor { days: 90 };
+        let now = Utc::now();
+        let element = element("2001", &[]);
+
+        assert!(rule.matches(&element, None, now));
+        assert!(rule.matches(&element, Some(now - Duration::days(91)), now));
+        assert!(!rule.matches(&element, Some(now - Duration::days(10)), now));
+    }
+}
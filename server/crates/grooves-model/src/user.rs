@@ -2,10 +2,20 @@ use rspotify::Token;
 use serde::{Deserialize, Serialize};
 use sqlx::FromRow;
 
+use crate::ExplicitFilter;
+
 #[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize, FromRow)]
 pub struct User {
     pub id: i32,
     pub spotify_id: String,
     #[sqlx(json)]
     pub token: Option<Token>,
+    /// Used for plays that don't choose their own filter
+    pub explicit_filter: ExplicitFilter,
+}
+
+/// The parts of a user that they can change themselves
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize, FromRow)]
+pub struct UserSettings {
+    pub explicit_filter: ExplicitFilter,
 }
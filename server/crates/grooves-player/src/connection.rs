@@ -2,6 +2,7 @@ use std::sync::{Arc, Mutex};
 use std::task::{Poll, Waker};
 
 use futures::Future;
+use grooves_model::{Playlist, ResumePoint};
 use rspotify::AuthCodeSpotify;
 use tokio::sync::{mpsc, watch};
 use tokio::task;
@@ -44,6 +45,11 @@ impl Future for FutureConnection {
 pub struct PlayerConnection {
     pub sender: mpsc::UnboundedSender<Command>,
     pub receiver: watch::Receiver<Option<PlaybackInfo>>,
+    /// The playlist being played, including generated elements the user kept
+    pub playlist_receiver: watch::Receiver<Option<Playlist>>,
+    pub resume_receiver: watch::Receiver<Option<ResumePoint>>,
+    /// The album of each element made from one, as it starts playing
+    pub album_receiver: watch::Receiver<Option<String>>,
 }
 
 impl PlayerConnection {
@@ -52,13 +58,26 @@ impl PlayerConnection {
         info!("creating new player");
         let (manager_sender, player_receiver) = mpsc::unbounded_channel();
         let (player_sender, manager_receiver) = watch::channel(None);
-        let player = Player::new(spotify_client, player_sender, player_receiver);
+        let (playlist_sender, playlist_receiver) = watch::channel(None);
+        let (resume_sender, resume_receiver) = watch::channel(None);
+        let (album_sender, album_receiver) = watch::channel(None);
+        let player = Player::new(
+            spotify_client,
+            player_sender,
+            playlist_sender,
+            resume_sender,
+            album_sender,
+            player_receiver,
+        );
 
         task::spawn(player.run());
 
         Self {
             sender: manager_sender,
             receiver: manager_receiver,
+            playlist_receiver,
+            resume_receiver,
+            album_receiver,
         }
     }
 }
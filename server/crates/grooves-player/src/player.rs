@@ -1,10 +1,14 @@
+use std::collections::HashSet;
+
 use anyhow::anyhow;
 use chrono::Duration;
-use grooves_model::{Playlist, PlaylistElement, Song};
+use grooves_model::{ExplicitFilter, Playlist, PlaylistElement, ResumePoint, Song};
+use itertools::Itertools;
 use rand::seq::SliceRandom;
-use rand::thread_rng;
-use rspotify::model::{FullTrack, PlayableItem, RepeatState};
-use rspotify::prelude::OAuthClient;
+use rand::{thread_rng, Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+use rspotify::model::{FullTrack, Offset, PlayableItem, RepeatState};
+use rspotify::prelude::{Id, OAuthClient};
 use rspotify::{AuthCodeSpotify, ClientResult};
 use serde::{Deserialize, Serialize};
 use tokio::sync::{mpsc, watch};
@@ -14,7 +18,8 @@ use self::commands::Command;
 
 pub mod commands;
 pub mod error;
-use error::PlayerError;
+mod radio;
+use error::{InvalidPlayError, PlayerError};
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct PlaybackInfo {
@@ -22,6 +27,10 @@ pub struct PlaybackInfo {
     song_name: String,
     album_name: String,
     artists: String,
+    /// Whether the current element was added by radio mode and hasn't been kept yet
+    generated: bool,
+    /// Playing the same playlist with this seed gives the same order
+    seed: Option<u32>,
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
@@ -39,9 +48,141 @@ struct PlayerState {
 
     /// The index of the current song in the element
     current_song: usize,
+
+    /// Indices of playlist elements added by radio mode that the user hasn't kept
+    generated: HashSet<usize>,
+
+    /// The value of current_element when radio mode last tried to add elements
+    radio_attempted_at: Option<usize>,
+
+    /// How many elements the playlist had before radio mode added any
+    playlist_len: usize,
+
+    /// The seed the order was shuffled with. None if we resumed an order that was saved without one
+    seed: Option<u32>,
+
+    /// Songs and elements it leaves out are never played, and elements it leaves out aren't in
+    /// the order
+    explicit_filter: ExplicitFilter,
+
+    /// The user who's playing, which isn't necessarily the playlist's owner
+    user_id: i32,
 }
 
 impl PlayerState {
+    fn new(
+        playlist: Playlist,
+        element_index: Option<usize>,
+        song_index: Option<usize>,
+        seed: Option<u32>,
+        resume_point: Option<ResumePoint>,
+        explicit_filter: ExplicitFilter,
+        user_id: i32,
+    ) -> Result<Self, InvalidPlayError> {
+        validate_play(&playlist, element_index, song_index, explicit_filter)?;
+
+        let seed = seed.unwrap_or_else(|| thread_rng().gen());
+
+        let mut order = element_order(&playlist.elements, element_index, seed);
+        order.retain(|&i| explicit_filter.allows_element(&playlist.elements[i]));
+
+        let mut state = Self {
+            device_id: None,
+            order,
+            seed: Some(seed),
+            playlist_len: playlist.elements.len(),
+            playlist,
+            current_element: 0,
+            current_song: 0,
+            generated: HashSet::new(),
+            radio_attempted_at: None,
+            explicit_filter,
+            user_id,
+        };
+        state.reset_song();
+        if let Some(song_index) = song_index {
+            state.current_song = song_index;
+        }
+
+        if let Some(resume_point) = resume_point {
+            if !state.resume_from(resume_point) {
+                info!("resume point doesn't fit the playlist, starting over");
+            }
+        }
+
+        Ok(state)
+    }
+
+    /// Moves to where the resume point left off. Returns false if the playlist has changed too
+    /// much since the resume point was saved, or it was saved with a different explicit filter
+    fn resume_from(&mut self, resume_point: ResumePoint) -> bool {
+        let mut sorted = resume_point.element_order.clone();
+        sorted.sort_unstable();
+        let mut allowed = self.order.clone();
+        allowed.sort_unstable();
+        if sorted != allowed {
+            return false;
+        }
+
+        let (Ok(current_element), Ok(current_song)) = (
+            usize::try_from(resume_point.current_element),
+            usize::try_from(resume_point.current_song),
+        ) else {
+            return false;
+        };
+
+        let Some(&index) = resume_point.element_order.get(current_element) else {
+            return false;
+        };
+
+        let songs = &self.playlist.elements[index].songs;
+        if !songs
+            .get(current_song)
+            .is_some_and(|s| self.explicit_filter.allows_song(s))
+        {
+            return false;
+        }
+
+        self.order = resume_point.element_order;
+        self.seed = resume_point.seed.and_then(|s| u32::try_from(s).ok());
+        self.current_element = current_element;
+        self.current_song = current_song;
+        true
+    }
+
+    /// Where we are in the playlist, leaving out anything radio mode added.
+    /// None if the playlist isn't saved or a generated element is playing
+    fn resume_point(&self) -> Option<ResumePoint> {
+        if self.playlist.is_ephemeral() || self.get_current_index() >= self.playlist_len {
+            return None;
+        }
+
+        let element_order: Vec<usize> = self
+            .order
+            .iter()
+            .copied()
+            .filter(|&i| i < self.playlist_len)
+            .collect();
+
+        let current_element = self.order[..self.current_element]
+            .iter()
+            .filter(|&&i| i < self.playlist_len)
+            .count();
+
+        Some(ResumePoint {
+            user_id: self.user_id,
+            playlist_id: self.playlist.id,
+            element_order,
+            current_element: current_element as i32,
+            current_song: self.current_song as i32,
+            seed: self.seed.map(i64::from),
+        })
+    }
+
+    fn get_current_index(&self) -> usize {
+        self.order[self.current_element]
+    }
+
     fn get_current_element(&self) -> &PlaylistElement {
         let index = self.order[self.current_element];
         &self.playlist.elements[index]
@@ -53,9 +194,18 @@ impl PlayerState {
         &element.songs[self.current_song]
     }
 
+    /// Moves to the start of the current element
+    fn reset_song(&mut self) {
+        // Elements without a playable song are never in the order
+        self.current_song = self
+            .explicit_filter
+            .first_song(self.get_current_element())
+            .unwrap_or(0);
+    }
+
     fn increment_current(&mut self) {
         self.current_element = (self.current_element + 1) % self.order.len();
-        self.current_song = 0;
+        self.reset_song();
     }
 
     fn decrement_current(&mut self) {
@@ -64,7 +214,7 @@ impl PlayerState {
         } else {
             self.current_element -= 1;
         }
-        self.current_song = 0;
+        self.reset_song();
     }
 
     fn get_playback_info(&self) -> PlaybackInfo {
@@ -76,6 +226,78 @@ impl PlayerState {
             song_name: song.name.clone(),
             album_name: element.name.clone(),
             artists: song.artists.clone(),
+            generated: self.generated.contains(&self.get_current_index()),
+            seed: self.seed,
+        }
+    }
+
+    /// How many elements are left to play before the order wraps around
+    fn remaining(&self) -> usize {
+        self.order.len() - self.current_element - 1
+    }
+
+    /// The elements that have been played so far, most recent last
+    fn played_elements(&self) -> Vec<&PlaylistElement> {
+        self.order[..=self.current_element]
+            .iter()
+            .map(|&i| &self.playlist.elements[i])
+            .collect()
+    }
+
+    fn append_generated(&mut self, elements: Vec<PlaylistElement>) {
+        for element in elements {
+            if !self.explicit_filter.allows_element(&element) {
+                continue;
+            }
+
+            let index = self.playlist.elements.len();
+            self.playlist.elements.push(element);
+            self.order.push(index);
+            self.generated.insert(index);
+        }
+    }
+
+    /// Returns false if the current element wasn't generated
+    fn keep_current(&mut self) -> bool {
+        self.generated.remove(&self.get_current_index())
+    }
+
+    /// Drops the current element from the order if it was generated, moving on to the next one.
+    /// Returns false if the current element wasn't generated
+    fn discard_current(&mut self) -> bool {
+        if !self.generated.remove(&self.get_current_index()) {
+            return false;
+        }
+
+        self.order.remove(self.current_element);
+        if self.current_element == self.order.len() {
+            self.current_element = 0;
+        }
+        self.reset_song();
+
+        true
+    }
+
+    /// The playlist without any generated elements the user hasn't kept. Elements the explicit
+    /// filter left out of the order are still part of the playlist
+    fn kept_playlist(&self) -> Playlist {
+        let elements = self
+            .playlist
+            .elements
+            .iter()
+            .enumerate()
+            .filter(|(i, _)| {
+                *i < self.playlist_len || (self.order.contains(i) && !self.generated.contains(i))
+            })
+            .map(|(_, e)| e.clone())
+            .collect();
+
+        Playlist {
+            id: self.playlist.id,
+            name: self.playlist.name.clone(),
+            owner_id: self.playlist.owner_id,
+            version: self.playlist.version,
+            elements,
         }
     }
 }
@@ -83,8 +305,12 @@ impl PlayerState {
 pub struct Player {
     spotify_client: AuthCodeSpotify,
     sender: watch::Sender<Option<PlaybackInfo>>,
+    playlist_sender: watch::Sender<Option<Playlist>>,
+    resume_sender: watch::Sender<Option<ResumePoint>>,
+    album_sender: watch::Sender<Option<String>>,
     receiver: mpsc::UnboundedReceiver<Command>,
     playback_state: Option<PlayerState>,
+    radio: bool,
 }
 
 enum TickResult {
@@ -96,13 +322,20 @@ impl Player {
     pub fn new(
         spotify_client: AuthCodeSpotify,
         sender: watch::Sender<Option<PlaybackInfo>>,
+        playlist_sender: watch::Sender<Option<Playlist>>,
+        resume_sender: watch::Sender<Option<ResumePoint>>,
+        album_sender: watch::Sender<Option<String>>,
         receiver: mpsc::UnboundedReceiver<Command>,
     ) -> Self {
         Self {
             spotify_client,
             sender,
+            playlist_sender,
+            resume_sender,
+            album_sender,
             receiver,
             playback_state: None,
+            radio: false,
         }
     }
 
@@ -111,7 +344,13 @@ impl Player {
 
         loop {
             if let Ok(command) = self.receiver.try_recv() {
-                self.handle_command(command).await?;
+                match self.handle_command(command).await {
+                    Ok(()) => {}
+                    Err(PlayerError::InvalidPlay(e)) => {
+                        warn!(error=?e, "ignoring invalid play command");
+                    }
+                    Err(e) => return Err(e),
+                }
             }
 
             if self.playback_state.is_some() {
@@ -129,6 +368,10 @@ impl Player {
                         failures += 1;
                     }
                 }
+
+                if let Err(e) = self.extend_radio().await {
+                    info!(error=?e, "radio failed to add elements");
+                }
             };
 
             if failures >= 5 {
@@ -151,16 +394,21 @@ impl Player {
 
         let playback_state = self.playback_state.as_mut().unwrap();
         let current_element = playback_state.get_current_element();
+        let first_song = playback_state
+            .explicit_filter
+            .first_song(current_element)
+            .map(|i| &current_element.songs[i]);
 
         // If the playback stopped at the first song of the element with 0 progress then we need to play the next element
         if !playback.is_playing {
             if let Some(prog) = playback.progress {
                 if let Some(PlayableItem::Track(song)) = &playback.item {
                     if let Some(id) = &song.id {
-                        if current_element.songs[0].spotify_id == *id && prog == Duration::zero() {
+                        if first_song.is_some_and(|s| s.spotify_id == *id)
+                            && prog == Duration::zero()
+                        {
                             playback_state.increment_current();
-                            let element = playback_state.get_current_element();
-                            play_element(&self.spotify_client, element).await?;
+                            self.play_current().await?;
 
                             return Ok(TickResult::Changed);
                         }
@@ -181,7 +429,9 @@ impl Player {
             .get_current_element()
             .songs
             .iter()
-            .position(|s| s.spotify_id == playing_id);
+            .position(|s| {
+                playback_state.explicit_filter.allows_song(s) && s.spotify_id == playing_id
+            });
 
         if let Some(idx) = playing_index {
             if idx != playback_state.current_song {
@@ -199,28 +449,36 @@ impl Player {
 
     async fn handle_command(&mut self, command: Command) -> Result<(), PlayerError> {
         if let Command::Play {
+            user_id,
             playlist,
             element_index,
-            ..
+            song_index,
+            seed,
+            resume_point,
+            explicit_filter,
         } = command
         {
-            let new_state = PlayerState {
-                device_id: None,
-                order: generate_order(playlist.elements.len(), element_index),
+            let new_state = PlayerState::new(
                 playlist,
-                current_element: 0,
-                current_song: 0,
-            };
+                element_index,
+                song_index,
+                seed,
+                resume_point,
+                explicit_filter,
+                user_id,
+            )?;
 
             self.playback_state = Some(new_state);
-            let playback_state = self.playback_state.as_ref().unwrap();
-            let element = playback_state.get_current_element();
-
-            let res = play_element(&self.spotify_client, element).await;
+            let res = self.play_current().await;
 
             if res.is_ok() && self.send_state().await.is_err() {
                 return Err(PlayerError::ChannelError);
             }
+            return self.send_playlist();
+        }
+
+        if let Command::SetRadio { enabled } = command {
+            self.radio = enabled;
             return Ok(());
         }
 
@@ -229,7 +487,7 @@ impl Player {
         };
 
         match command {
-            Command::Play { .. } => unreachable!(),
+            Command::Play { .. } | Command::SetRadio { .. } => unreachable!(),
             Command::Pause => self.spotify_client.pause_playback(None).await?,
             Command::Resume => self.spotify_client.resume_playback(None, None).await?,
             Command::NextSong => self.spotify_client.next_track(None).await?,
@@ -237,8 +495,7 @@ impl Player {
             Command::NextElement => {
                 playback_state.increment_current();
 
-                let element = playback_state.get_current_element();
-                let res = play_element(&self.spotify_client, element).await;
+                let res = self.play_current().await;
 
                 if res.is_ok() && self.send_state().await.is_err() {
                     return Err(PlayerError::ChannelError);
@@ -248,14 +505,30 @@ impl Player {
             Command::PrevElement => {
                 playback_state.decrement_current();
 
-                let element = playback_state.get_current_element();
-                let res = play_element(&self.spotify_client, element).await;
+                let res = self.play_current().await;
 
                 if res.is_ok() {
                     self.send_state().await?
                 }
             }
 
+            Command::KeepGenerated => {
+                if playback_state.keep_current() {
+                    self.send_state().await?;
+                    self.send_playlist()?;
+                }
+            }
+
+            Command::DiscardGenerated => {
+                if playback_state.discard_current() {
+                    let res = self.play_current().await;
+
+                    if res.is_ok() {
+                        self.send_state().await?
+                    }
+                }
+            }
+
             Command::AddToQueue | Command::RemoveFromQueue | Command::Exit => {
                 warn!("Unimplemented command");
             }
@@ -264,8 +537,36 @@ impl Player {
         Ok(())
     }
 
+    /// Starts the current element from the current song
+    async fn play_current(&self) -> ClientResult<()> {
+        let Some(playback_state) = &self.playback_state else {
+            return Ok(());
+        };
+
+        let element = playback_state.get_current_element();
+        play_element(
+            &self.spotify_client,
+            element,
+            playback_state.current_song,
+            playback_state.explicit_filter,
+        )
+        .await?;
+
+        if let Some(album_id) = &element.album_id {
+            // Nothing might be recording album plays, which is fine
+            let _ = self.album_sender.send(Some(album_id.id().to_owned()));
+        }
+
+        Ok(())
+    }
+
     async fn send_state(&self) -> Result<(), PlayerError> {
         if let Some(playback_state) = &self.playback_state {
+            if let Some(resume_point) = playback_state.resume_point() {
+                // Nothing might be saving resume points, which is fine
+                let _ = self.resume_sender.send(Some(resume_point));
+            }
+
             let playback_info = playback_state.get_playback_info();
             if self.sender.send(Some(playback_info)).is_ok() {
                 return Ok(());
@@ -274,32 +575,603 @@ impl Player {
 
         Err(PlayerError::NoPlayback)
     }
+
+    fn send_playlist(&self) -> Result<(), PlayerError> {
+        let playlist = self.playback_state.as_ref().map(PlayerState::kept_playlist);
+
+        self.playlist_sender
+            .send(playlist)
+            .map_err(|_| PlayerError::ChannelError)
+    }
+
+    /// Adds generated elements to the end of the order if radio mode is on and we're running out
+    async fn extend_radio(&mut self) -> ClientResult<()> {
+        let Some(playback_state) = self.playback_state.as_mut() else {
+            return Ok(());
+        };
+
+        if !self.radio
+            || playback_state.remaining() >= radio::RADIO_THRESHOLD
+            || playback_state.radio_attempted_at == Some(playback_state.current_element)
+        {
+            return Ok(());
+        }
+
+        // Only try once per element so we don't hammer spotify when there's nothing to add
+        playback_state.radio_attempted_at = Some(playback_state.current_element);
+
+        let elements = radio::generate_elements(
+            &self.spotify_client,
+            &playback_state.played_elements(),
+            &playback_state.playlist.elements,
+        )
+        .await?;
+
+        info!(count = elements.len(), "radio adding elements");
+        playback_state.append_generated(elements);
+
+        Ok(())
+    }
 }
 
 async fn play_element(
     spotify_client: &AuthCodeSpotify,
     element: &PlaylistElement,
+    song_index: usize,
+    explicit_filter: ExplicitFilter,
 ) -> ClientResult<()> {
     spotify_client.repeat(RepeatState::Off, None).await?;
     spotify_client.shuffle(false, None).await?;
 
-    let song_ids = element.songs.iter().map(|s| s.spotify_id.clone().into());
+    // Only the selected songs are queued, so the element ends after its last one
+    let song_ids = element
+        .songs
+        .iter()
+        .filter(|s| explicit_filter.allows_song(s))
+        .map(|s| s.spotify_id.clone().into());
+    let offset = (Some(song_index) != explicit_filter.first_song(element))
+        .then(|| Offset::Uri(element.songs[song_index].spotify_id.uri()));
 
     spotify_client
-        .start_uris_playback(song_ids, None, None, None)
+        .start_uris_playback(song_ids, None, offset, None)
         .await
 }
 
-fn generate_order(len: usize, start_index: Option<usize>) -> Vec<usize> {
+/// Checks that a playlist can be played starting from the given indices
+///
+/// Elements without songs that can be played are skipped like the ones the explicit filter
+/// leaves out, so they only make the play invalid when it starts at one of them
+pub fn validate_play(
+    playlist: &Playlist,
+    element_index: Option<usize>,
+    song_index: Option<usize>,
+    explicit_filter: ExplicitFilter,
+) -> Result<(), InvalidPlayError> {
+    let elements = &playlist.elements;
+
+    if !elements.iter().any(|e| explicit_filter.allows_element(e)) {
+        return Err(InvalidPlayError::EmptyPlaylist);
+    }
+
+    match (element_index, song_index) {
+        (Some(index), _) if index >= elements.len() => {
+            Err(InvalidPlayError::ElementIndexOutOfRange {
+                index,
+                len: elements.len(),
+            })
+        }
+        (Some(element_index), Some(index)) if index >= elements[element_index].songs.len() => {
+            Err(InvalidPlayError::SongIndexOutOfRange {
+                index,
+                len: elements[element_index].songs.len(),
+            })
+        }
+        (Some(index), _) if !ExplicitFilter::Allow.allows_element(&elements[index]) => {
+            Err(InvalidPlayError::EmptyElement(index))
+        }
+        (Some(index), _) if !explicit_filter.allows_element(&elements[index]) => {
+            Err(InvalidPlayError::ElementFiltered(index))
+        }
+        (Some(element_index), Some(index))
+            if !explicit_filter.allows_song(&elements[element_index].songs[index]) =>
+        {
+            Err(InvalidPlayError::SongNotPlayable(index))
+        }
+        (None, Some(_)) => Err(InvalidPlayError::SongIndexWithoutElement),
+        _ => Ok(()),
+    }
+}
+
+/// The order to play elements in. Elements with higher shuffle weights tend to come earlier
+///
+/// When every weight is the same this is `generate_order`, so seeds saved before weights
+/// existed still give the same orders
+pub fn element_order(
+    elements: &[PlaylistElement],
+    start_index: Option<usize>,
+    seed: u32,
+) -> Vec<usize> {
+    let weights: Vec<u32> = elements
+        .iter()
+        .map(PlaylistElement::shuffle_weight)
+        .collect();
+
+    if weights.iter().all_equal() {
+        generate_order(weights.len(), start_index, seed)
+    } else {
+        generate_weighted_order(&weights, start_index, seed)
+    }
+}
+
+/// The same length, start index and seed always give the same order
+pub fn generate_order(len: usize, start_index: Option<usize>, seed: u32) -> Vec<usize> {
+    // ChaCha is used rather than StdRng because its output is guaranteed not to change
+    let mut rng = ChaCha8Rng::seed_from_u64(seed.into());
     let mut nums: Vec<usize> = (0..len).collect();
 
     if let Some(start_index) = start_index {
         nums.remove(start_index);
-        nums.shuffle(&mut thread_rng());
+        nums.shuffle(&mut rng);
         nums.insert(0, start_index);
     } else {
-        nums.shuffle(&mut thread_rng());
+        nums.shuffle(&mut rng);
     }
 
     nums
 }
+
+/// Each index gets a random key of u^(1/weight) and they're sorted by key, which is the same as
+/// repeatedly picking the next index with probability proportional to its weight
+fn generate_weighted_order(weights: &[u32], start_index: Option<usize>, seed: u32) -> Vec<usize> {
+    let mut rng = ChaCha8Rng::seed_from_u64(seed.into());
+
+    let mut keyed: Vec<(f64, usize)> = (0..weights.len())
+        .filter(|&i| Some(i) != start_index)
+        .map(|i| {
+            let u: f64 = rng.gen();
+            (u.powf(1.0 / f64::from(weights[i].max(1))), i)
+        })
+        .collect();
+    keyed.sort_by(|a, b| b.0.total_cmp(&a.0));
+
+    start_index
+        .into_iter()
+        .chain(keyed.into_iter().map(|(_, i)| i))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use grooves_model::{Playlist, PlaylistElement, Song};
+    use proptest::prelude::*;
+
+    use super::*;
+
+    fn playlist(element_sizes: &[usize]) -> Playlist {
+        let elements = element_sizes
+            .iter()
+            .enumerate()
+            .map(|(e, &size)| PlaylistElement {
+                name: format!("element {e}"),
+                songs: (0..size)
+                    .map(|s| Song::test(&format!("e{e}s{s}")))
+                    .collect(),
+                ..Default::default()
+            })
+            .collect();
+
+        Playlist {
+            id: 1,
+            name: "test".to_owned(),
+            owner_id: 0,
+            version: 1,
+            elements,
+        }
+    }
+
+    /// A play of the whole playlist by user 0, without a seed, resume point or explicit filter.
+    /// Tests change whichever of those they need with struct update syntax
+    struct Play {
+        playlist: Playlist,
+        element_index: Option<usize>,
+        song_index: Option<usize>,
+        seed: Option<u32>,
+        resume_point: Option<ResumePoint>,
+        explicit_filter: ExplicitFilter,
+        user_id: i32,
+    }
+
+    impl Play {
+        fn new(playlist: Playlist) -> Self {
+            Self {
+                playlist,
+                element_index: None,
+                song_index: None,
+                seed: None,
+                resume_point: None,
+                explicit_filter: ExplicitFilter::Allow,
+                user_id: 0,
+            }
+        }
+
+        fn start(self) -> Result<PlayerState, InvalidPlayError> {
+            PlayerState::new(
+                self.playlist,
+                self.element_index,
+                self.song_index,
+                self.seed,
+                self.resume_point,
+                self.explicit_filter,
+                self.user_id,
+            )
+        }
+    }
+
+    #[test]
+    fn rejects_empty_playlist() {
+        assert_eq!(
+            Play::new(playlist(&[])).start().unwrap_err(),
+            InvalidPlayError::EmptyPlaylist
+        );
+    }
+
+    #[test]
+    fn rejects_starting_at_empty_element() {
+        assert_eq!(
+            Play {
+                element_index: Some(1),
+                ..Play::new(playlist(&[2, 0, 1]))
+            }
+            .start()
+            .unwrap_err(),
+            InvalidPlayError::EmptyElement(1)
+        );
+    }
+
+    #[test]
+    fn rejects_song_index_without_element() {
+        assert_eq!(
+            Play {
+                song_index: Some(0),
+                ..Play::new(playlist(&[2]))
+            }
+            .start()
+            .unwrap_err(),
+            InvalidPlayError::SongIndexWithoutElement
+        );
+    }
+
+    #[test]
+    fn rejects_starting_at_element_with_every_song_excluded() {
+        let mut playlist = playlist(&[2, 2]);
+        for song in &mut playlist.elements[1].songs {
+            song.excluded = true;
+        }
+
+        assert_eq!(
+            Play {
+                element_index: Some(1),
+                ..Play::new(playlist)
+            }
+            .start()
+            .unwrap_err(),
+            InvalidPlayError::EmptyElement(1)
+        );
+    }
+
+    #[test]
+    fn skips_elements_spotify_made_unavailable() {
+        let mut playlist = playlist(&[2, 2, 2]);
+        for song in &mut playlist.elements[1].songs {
+            song.unavailable = true;
+        }
+
+        let state = Play::new(playlist).start().unwrap();
+        assert_eq!(state.order.len(), 2);
+        assert!(!state.order.contains(&1));
+    }
+
+    #[test]
+    fn rejects_starting_at_excluded_song() {
+        let mut playlist = playlist(&[3]);
+        playlist.elements[0].songs[1].excluded = true;
+
+        assert_eq!(
+            Play {
+                element_index: Some(0),
+                song_index: Some(1),
+                ..Play::new(playlist)
+            }
+            .start()
+            .unwrap_err(),
+            InvalidPlayError::SongNotPlayable(1)
+        );
+    }
+
+    #[test]
+    fn elements_start_at_first_playable_song() {
+        let mut playlist = playlist(&[3, 3]);
+        for element in &mut playlist.elements {
+            element.songs[0].excluded = true;
+            element.songs[1].unavailable = true;
+        }
+
+        let mut state = Play {
+            element_index: Some(0),
+            ..Play::new(playlist)
+        }
+        .start()
+        .unwrap();
+        assert_eq!(state.current_song, 2);
+
+        state.increment_current();
+        assert_eq!(state.current_song, 2);
+    }
+
+    #[test]
+    fn generated_elements_can_be_kept_or_discarded() {
+        let mut state = Play::new(playlist(&[1])).start().unwrap();
+        let generated = playlist(&[2, 3]).elements;
+        state.append_generated(generated.clone());
+
+        assert_eq!(state.remaining(), 2);
+        assert!(!state.keep_current());
+
+        state.increment_current();
+        assert!(state.get_playback_info().generated);
+        assert!(state.keep_current());
+        assert!(!state.get_playback_info().generated);
+
+        state.increment_current();
+        assert!(state.discard_current());
+        assert_eq!(state.order.len(), 2);
+        assert_eq!(state.current_element, 0);
+
+        let kept = state.kept_playlist();
+        assert_eq!(kept.elements.len(), 2);
+        assert_eq!(kept.elements[1], generated[0]);
+    }
+
+    #[test]
+    fn resume_point_skips_generated_elements() {
+        let mut state = Play {
+            element_index: Some(1),
+            ..Play::new(playlist(&[1, 1]))
+        }
+        .start()
+        .unwrap();
+        state.append_generated(playlist(&[1]).elements);
+
+        // Move the generated element to the middle of the order
+        state.order = vec![1, 2, 0];
+        state.current_element = 2;
+
+        let resume_point = state.resume_point().unwrap();
+        assert_eq!(resume_point.element_order, vec![1, 0]);
+        assert_eq!(resume_point.current_element, 1);
+
+        state.current_element = 1;
+        assert!(state.resume_point().is_none());
+    }
+
+    #[test]
+    fn resume_point_belongs_to_the_player_not_the_owner() {
+        let state = Play {
+            user_id: 7,
+            ..Play::new(playlist(&[1]))
+        }
+        .start()
+        .unwrap();
+
+        assert_eq!(state.resume_point().unwrap().user_id, 7);
+    }
+
+    #[test]
+    fn stale_resume_point_is_ignored() {
+        let state = Play::new(playlist(&[1, 1])).start().unwrap();
+        let mut resume_point = state.resume_point().unwrap();
+        resume_point.element_order.push(2);
+
+        let state = Play {
+            resume_point: Some(resume_point),
+            ..Play::new(playlist(&[1, 1]))
+        }
+        .start()
+        .unwrap();
+        assert_eq!(state.current_element, 0);
+        assert_eq!(state.order.len(), 2);
+    }
+
+    #[test]
+    fn explicit_filter_skips_songs_and_elements() {
+        let mut playlist = playlist(&[2, 2, 3]);
+        playlist.elements[0].songs[0].explicit = true;
+        for song in &mut playlist.elements[1].songs {
+            song.explicit = true;
+        }
+        playlist.elements[2].songs[0].explicit = true;
+        playlist.elements[2].songs[1].explicit = true;
+
+        let state = Play {
+            element_index: Some(0),
+            explicit_filter: ExplicitFilter::SkipTracks,
+            ..Play::new(playlist.clone())
+        }
+        .start()
+        .unwrap();
+        assert_eq!(state.order, vec![0, 2]);
+        assert_eq!(state.current_song, 1);
+        assert_eq!(state.kept_playlist().elements.len(), 3);
+
+        let state = Play {
+            explicit_filter: ExplicitFilter::SkipElements,
+            ..Play::new(playlist.clone())
+        }
+        .start()
+        .unwrap();
+        assert_eq!(state.order, vec![0]);
+
+        assert_eq!(
+            Play {
+                element_index: Some(2),
+                explicit_filter: ExplicitFilter::SkipElements,
+                ..Play::new(playlist.clone())
+            }
+            .start()
+            .unwrap_err(),
+            InvalidPlayError::ElementFiltered(2)
+        );
+        assert_eq!(
+            Play {
+                element_index: Some(0),
+                song_index: Some(0),
+                explicit_filter: ExplicitFilter::SkipTracks,
+                ..Play::new(playlist)
+            }
+            .start()
+            .unwrap_err(),
+            InvalidPlayError::SongNotPlayable(0)
+        );
+    }
+
+    #[test]
+    fn resume_point_from_another_explicit_filter_is_ignored() {
+        let mut playlist = playlist(&[1, 1, 1]);
+        playlist.elements[1].songs[0].explicit = true;
+
+        let state = Play::new(playlist.clone()).start().unwrap();
+        let resume_point = state.resume_point();
+
+        let state = Play {
+            resume_point,
+            explicit_filter: ExplicitFilter::SkipTracks,
+            ..Play::new(playlist)
+        }
+        .start()
+        .unwrap();
+        assert_eq!(state.order.len(), 2);
+        assert!(!state.order.contains(&1));
+    }
+
+    proptest! {
+        #[test]
+        fn out_of_range_indices_are_rejected(
+            sizes in prop::collection::vec(1usize..5, 1..10),
+            extra in 0usize..10,
+        ) {
+            let len = sizes.len();
+            prop_assert_eq!(
+                Play { element_index: Some(len + extra), ..Play::new(playlist(&sizes)) }.start()
+                    .unwrap_err(),
+                InvalidPlayError::ElementIndexOutOfRange { index: len + extra, len }
+            );
+
+            let songs = sizes[0];
+            prop_assert_eq!(
+                Play { element_index: Some(0), song_index: Some(songs + extra), ..Play::new(playlist(&sizes)) }.start()
+                    .unwrap_err(),
+                InvalidPlayError::SongIndexOutOfRange { index: songs + extra, len: songs }
+            );
+        }
+
+        #[test]
+        fn order_is_a_permutation_starting_at_element_index(
+            (sizes, start) in prop::collection::vec(1usize..5, 1..20)
+                .prop_flat_map(|sizes| {
+                    let len = sizes.len();
+                    (Just(sizes), prop::option::of(0..len))
+                }),
+        ) {
+            let state = Play { element_index: start, ..Play::new(playlist(&sizes)) }.start().unwrap();
+
+            let mut sorted = state.order.clone();
+            sorted.sort_unstable();
+            prop_assert_eq!(sorted, (0..sizes.len()).collect::<Vec<_>>());
+
+            if let Some(start) = start {
+                prop_assert_eq!(state.order[0], start);
+            }
+        }
+
+        #[test]
+        fn resuming_restores_position(
+            sizes in prop::collection::vec(1usize..5, 1..20),
+            moves in 0usize..50,
+            song in 0usize..5,
+        ) {
+            let mut state = Play::new(playlist(&sizes)).start().unwrap();
+            for _ in 0..moves {
+                state.increment_current();
+            }
+            state.current_song = song % state.get_current_element().songs.len();
+
+            let resumed =
+                Play { resume_point: state.resume_point(), ..Play::new(playlist(&sizes)) }.start()
+                    .unwrap();
+
+            prop_assert_eq!(resumed.order, state.order);
+            prop_assert_eq!(resumed.current_element, state.current_element);
+            prop_assert_eq!(resumed.current_song, state.current_song);
+            prop_assert_eq!(resumed.seed, state.seed);
+        }
+
+        #[test]
+        fn same_seed_gives_same_order(
+            (len, start) in (1usize..50).prop_flat_map(|len| (Just(len), prop::option::of(0..len))),
+            seed in any::<u32>(),
+        ) {
+            prop_assert_eq!(generate_order(len, start, seed), generate_order(len, start, seed));
+
+            let state = Play { element_index: start, seed: Some(seed), ..Play::new(playlist(&vec![1; len])) }.start()
+                .unwrap();
+            prop_assert_eq!(state.seed, Some(seed));
+            prop_assert_eq!(state.order, generate_order(len, start, seed));
+        }
+
+        #[test]
+        fn weighted_order_is_a_permutation(
+            (weights, start) in prop::collection::vec(1i32..=10, 1..50)
+                .prop_flat_map(|w| { let len = w.len(); (Just(w), prop::option::of(0..len)) }),
+            seed in any::<u32>(),
+        ) {
+            let mut playlist = playlist(&vec![1; weights.len()]);
+            for (element, weight) in playlist.elements.iter_mut().zip(&weights) {
+                element.shuffle_weight = Some(*weight);
+            }
+
+            let order = element_order(&playlist.elements, start, seed);
+            prop_assert_eq!(&order, &element_order(&playlist.elements, start, seed));
+            if let Some(start) = start {
+                prop_assert_eq!(order[0], start);
+            }
+
+            let mut sorted = order.clone();
+            sorted.sort_unstable();
+            prop_assert_eq!(sorted, (0..weights.len()).collect::<Vec<_>>());
+        }
+
+        #[test]
+        fn navigation_stays_in_bounds(
+            sizes in prop::collection::vec(1usize..5, 1..20),
+            moves in prop::collection::vec(any::<bool>(), 0..50),
+        ) {
+            let len = sizes.len();
+            let mut state = Play::new(playlist(&sizes)).start().unwrap();
+
+            for forward in moves {
+                let before = state.current_element;
+
+                if forward {
+                    state.increment_current();
+                    prop_assert_eq!(state.current_element, (before + 1) % len);
+                } else {
+                    state.decrement_current();
+                    prop_assert_eq!(state.current_element, (before + len - 1) % len);
+                }
+
+                prop_assert_eq!(state.current_song, 0);
+                state.get_playback_info();
+            }
+        }
+    }
+}
@@ -1,13 +1,23 @@
-use grooves_model::Playlist;
+use grooves_model::{ExplicitFilter, Playlist, ResumePoint};
 use serde::{Deserialize, Serialize};
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
 #[serde(tag = "type", rename_all = "snake_case")]
 pub enum Command {
     Play {
+        /// The user who's playing, whose resume point this play saves
+        user_id: i32,
         playlist: Playlist,
         element_index: Option<usize>,
         song_index: Option<usize>,
+        /// Shuffle with this seed, or a random one if None
+        #[serde(default)]
+        seed: Option<u32>,
+        /// Continue from here instead of starting a new order, if it still fits the playlist
+        #[serde(default)]
+        resume_point: Option<ResumePoint>,
+        #[serde(default)]
+        explicit_filter: ExplicitFilter,
     },
     Pause,
     Resume,
@@ -15,6 +25,13 @@ pub enum Command {
     PrevSong,
     NextElement,
     PrevElement,
+    SetRadio {
+        enabled: bool,
+    },
+    /// Keep the current element if it was added by radio mode
+    KeepGenerated,
+    /// Skip and drop the current element if it was added by radio mode
+    DiscardGenerated,
     AddToQueue,
     RemoveFromQueue,
     Exit,
@@ -4,14 +4,46 @@ use rspotify::ClientError;
 pub enum PlayerError {
     ChannelError,
     CommandError,
+    InvalidPlay(InvalidPlayError),
     NoPlayback,
     SpotifyError,
     TooManyErrors,
     OtherError(String),
 }
 
+/// Reasons a `Command::Play` can't be started
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum InvalidPlayError {
+    /// There are no elements with songs that can be played, or the explicit filter leaves out
+    /// all of them
+    EmptyPlaylist,
+    /// The play was started at an element with no songs that can be played
+    EmptyElement(usize),
+    ElementIndexOutOfRange {
+        index: usize,
+        len: usize,
+    },
+    SongIndexOutOfRange {
+        index: usize,
+        len: usize,
+    },
+    /// The element at this index is left out by the explicit filter
+    ElementFiltered(usize),
+    /// The song at the index is excluded from its element, unavailable or explicit and
+    /// filtered out
+    SongNotPlayable(usize),
+    /// A song index was given without saying which element it belongs to
+    SongIndexWithoutElement,
+}
+
 impl From<ClientError> for PlayerError {
     fn from(_value: ClientError) -> Self {
         Self::SpotifyError
     }
 }
+
+impl From<InvalidPlayError> for PlayerError {
+    fn from(value: InvalidPlayError) -> Self {
+        Self::InvalidPlay(value)
+    }
+}
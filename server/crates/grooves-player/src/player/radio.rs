@@ -0,0 +1,119 @@
+use std::collections::HashSet;
+
+use grooves_model::PlaylistElement;
+use itertools::Itertools;
+use rand::seq::SliceRandom;
+use rand::thread_rng;
+use rspotify::model::{AlbumId, ArtistId, RecommendationsAttribute, TrackId};
+use rspotify::prelude::BaseClient;
+use rspotify::{AuthCodeSpotify, ClientResult};
+
+/// Radio mode kicks in once there are fewer than this many elements left to play
+pub const RADIO_THRESHOLD: usize = 2;
+
+/// How many elements radio mode adds at a time
+const RADIO_BATCH_SIZE: usize = 5;
+
+/// Spotify allows at most 5 seeds per recommendation request
+const SEED_TRACKS: usize = 3;
+const SEED_ARTISTS: usize = 2;
+
+const RECOMMENDATION_LIMIT: u32 = 50;
+
+/// The most albums spotify will return from a single request
+const ALBUMS_PER_REQUEST: usize = 20;
+
+/// Generates new album elements similar to the ones that were played
+///
+/// `played` should be the most recently played elements last. Albums which share a track with
+/// `existing` are skipped so the radio doesn't repeat anything already in the playlist
+pub async fn generate_elements(
+    spotify_client: &AuthCodeSpotify,
+    played: &[&PlaylistElement],
+    existing: &[PlaylistElement],
+) -> ClientResult<Vec<PlaylistElement>> {
+    let seed_tracks: Vec<TrackId<'static>> = played
+        .iter()
+        .rev()
+        .filter_map(|e| e.songs.choose(&mut thread_rng()))
+        .map(|s| s.spotify_id.clone())
+        .take(SEED_TRACKS)
+        .collect();
+
+    if seed_tracks.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let seed_artists = related_artists(spotify_client, &seed_tracks).await?;
+
+    let recommendations = spotify_client
+        .recommendations(
+            Vec::<RecommendationsAttribute>::new(),
+            Some(seed_artists),
+            None::<Vec<&str>>,
+            Some(seed_tracks),
+            None,
+            Some(RECOMMENDATION_LIMIT),
+        )
+        .await?;
+
+    let album_ids: Vec<AlbumId<'static>> = recommendations
+        .tracks
+        .into_iter()
+        .filter_map(|t| t.album?.id)
+        .unique()
+        .take(RADIO_BATCH_SIZE * 2)
+        .collect();
+
+    let existing_tracks: HashSet<&TrackId<'static>> = existing
+        .iter()
+        .flat_map(|e| &e.songs)
+        .map(|s| &s.spotify_id)
+        .collect();
+
+    let mut elements = Vec::new();
+    for chunk in album_ids.chunks(ALBUMS_PER_REQUEST) {
+        let albums = spotify_client.albums(chunk.iter().cloned(), None).await?;
+        elements.extend(albums.into_iter().map(PlaylistElement::from));
+    }
+
+    Ok(elements
+        .into_iter()
+        .filter(|e| {
+            !e.songs.is_empty()
+                && !e
+                    .songs
+                    .iter()
+                    .any(|s| existing_tracks.contains(&s.spotify_id))
+        })
+        .take(RADIO_BATCH_SIZE)
+        .collect())
+}
+
+/// Picks artists related to the ones performing the seed tracks
+async fn related_artists(
+    spotify_client: &AuthCodeSpotify,
+    seed_tracks: &[TrackId<'static>],
+) -> ClientResult<Vec<ArtistId<'static>>> {
+    let tracks = spotify_client
+        .tracks(seed_tracks.iter().cloned(), None)
+        .await?;
+
+    let Some(artist_id) = tracks
+        .into_iter()
+        .flat_map(|t| t.artists)
+        .filter_map(|a| a.id)
+        .next()
+    else {
+        return Ok(Vec::new());
+    };
+
+    let mut related = spotify_client.artist_related_artists(artist_id).await?;
+    related.shuffle(&mut thread_rng());
+
+    Ok(related
+        .into_iter()
+        .map(|a| a.id)
+        .take(SEED_ARTISTS)
+        .collect())
+}
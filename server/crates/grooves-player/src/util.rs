@@ -11,7 +11,10 @@ pub fn client_with_token(mut token: Token) -> AuthCodeSpotify {
         "user-modify-playback-state",
         "user-read-playback-state",
         "playlist-read-private",
-        "user-read-private"
+        "user-read-private",
+        "user-library-read",
+        "playlist-modify-public",
+        "playlist-modify-private"
     );
 
     let oauth = OAuth {
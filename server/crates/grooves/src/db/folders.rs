@@ -0,0 +1,53 @@
+use grooves_model::PlaylistFolder;
+use sqlx::PgConnection;
+
+use crate::error::{GroovesError, GroovesResult};
+
+/// Gets one of the user's folders. Other users' folders are NotFound
+pub async fn require_folder(
+    conn: &mut PgConnection,
+    folder_id: i32,
+    user_id: i32,
+) -> GroovesResult<PlaylistFolder> {
+    let folder = sqlx::query_as("SELECT * FROM playlist_folder WHERE id = $1 AND user_id = $2")
+        .bind(folder_id)
+        .bind(user_id)
+        .fetch_optional(conn)
+        .await?
+        .ok_or(GroovesError::NotFound)?;
+
+    Ok(folder)
+}
+
+/// Locks all of the user's folders until the transaction ends. Moving a folder can make a
+/// cycle through any of them, so concurrent moves have to wait for each other
+pub async fn lock_folders(conn: &mut PgConnection, user_id: i32) -> GroovesResult<()> {
+    sqlx::query("SELECT id FROM playlist_folder WHERE user_id = $1 ORDER BY id FOR UPDATE")
+        .bind(user_id)
+        .execute(conn)
+        .await?;
+
+    Ok(())
+}
+
+/// Whether `folder_id` is `ancestor_id` or somewhere inside it
+pub async fn is_within(
+    conn: &mut PgConnection,
+    folder_id: i32,
+    ancestor_id: i32,
+) -> GroovesResult<bool> {
+    let within = sqlx::query_scalar(
+        r#"WITH RECURSIVE ancestor(id, parent_id) AS (
+                SELECT id, parent_id FROM playlist_folder WHERE id = $1
+                UNION
+                SELECT f.id, f.parent_id FROM playlist_folder f JOIN ancestor a ON f.id = a.parent_id
+            )
+            SELECT EXISTS (SELECT 1 FROM ancestor WHERE id = $2)"#,
+    )
+    .bind(folder_id)
+    .bind(ancestor_id)
+    .fetch_one(conn)
+    .await?;
+
+    Ok(within)
+}
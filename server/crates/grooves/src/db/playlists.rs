@@ -0,0 +1,680 @@
+use std::collections::HashMap;
+
+use grooves_model::{
+    ElementAnnotations, Playlist, PlaylistElement, PlaylistElementRow, PlaylistRole,
+    PlaylistSummary, SmartPlaylist, SmartRules, Song, SongRow,
+};
+use rspotify::model::AlbumId;
+use rspotify::prelude::Id;
+use sqlx::PgConnection;
+
+use crate::error::{GroovesError, GroovesResult};
+
+/// Fills in the elements of playlists that were loaded from the playlist table
+pub async fn load_elements(
+    conn: &mut PgConnection,
+    playlists: &mut [Playlist],
+) -> GroovesResult<()> {
+    let playlist_ids: Vec<i32> = playlists.iter().map(|p| p.id).collect();
+
+    let element_rows: Vec<PlaylistElementRow> = sqlx::query_as(
+        "SELECT * FROM playlist_element WHERE playlist_id = ANY($1) ORDER BY playlist_id, position",
+    )
+    .bind(&playlist_ids)
+    .fetch_all(&mut *conn)
+    .await?;
+
+    let element_ids: Vec<i32> = element_rows.iter().map(|e| e.id).collect();
+
+    let song_rows: Vec<SongRow> = sqlx::query_as(
+        "SELECT * FROM playlist_element_track WHERE element_id = ANY($1) ORDER BY element_id, position",
+    )
+    .bind(&element_ids)
+    .fetch_all(&mut *conn)
+    .await?;
+
+    let mut songs: HashMap<i32, Vec<Song>> = HashMap::new();
+    for row in song_rows {
+        songs
+            .entry(row.element_id)
+            .or_default()
+            .push(row.try_into()?);
+    }
+
+    let mut elements: HashMap<i32, Vec<PlaylistElement>> = HashMap::new();
+    for row in element_rows {
+        let element_songs = songs.remove(&row.id).unwrap_or_default();
+        elements
+            .entry(row.playlist_id)
+            .or_default()
+            .push(row.into_element(element_songs)?);
+    }
+
+    for playlist in playlists {
+        playlist.elements = elements.remove(&playlist.id).unwrap_or_default();
+    }
+
+    Ok(())
+}
+
+/// Which of a user's playlists match `search`, a LIKE pattern for the names of the playlists
+/// or their elements' names and artists
+const SUMMARY_CONDITIONS: &str = r#"m.user_id = $1 AND ($2 OR NOT m.archived)
+    AND ($3::TEXT IS NULL OR p.name ILIKE $3 OR EXISTS (
+        SELECT 1 FROM playlist_element s
+        WHERE s.playlist_id = p.id AND (s.name ILIKE $3 OR s.artists ILIKE $3)
+    ))"#;
+
+/// The most elements whose images a summary includes
+const COVER_IMAGES: i32 = 4;
+
+/// Summarizes the user's playlists that match. `order_by` is put into the query as is
+pub async fn select_summaries(
+    conn: &mut PgConnection,
+    user_id: i32,
+    include_archived: bool,
+    search: Option<&str>,
+    order_by: &str,
+    limit: i64,
+    offset: i64,
+) -> GroovesResult<Vec<PlaylistSummary>> {
+    let summaries = sqlx::query_as(&format!(
+        r#"SELECT p.id, p.name, {CURRENT_OWNER} AS owner_id, p.version,
+                m.folder_id, m.position, m.pinned, m.archived,
+                COUNT(e.id)::INT AS element_count,
+                COALESCE(SUM(e.duration_ms), 0)::BIGINT AS duration_ms,
+                COALESCE((ARRAY_AGG(e.image_url ORDER BY e.position) FILTER (WHERE e.id IS NOT NULL))[1:$6], '{{}}') AS cover_image_urls
+            FROM playlist p
+            JOIN playlist_member m ON m.playlist_id = p.id
+            LEFT JOIN playlist_element e ON e.playlist_id = p.id
+            WHERE {SUMMARY_CONDITIONS}
+            GROUP BY p.id, m.folder_id, m.position, m.pinned, m.archived
+            ORDER BY {order_by}
+            LIMIT $4 OFFSET $5"#
+    ))
+    .bind(user_id)
+    .bind(include_archived)
+    .bind(search)
+    .bind(limit)
+    .bind(offset)
+    .bind(COVER_IMAGES)
+    .fetch_all(conn)
+    .await?;
+
+    Ok(summaries)
+}
+
+/// How many playlists `select_summaries` would give without a limit
+pub async fn count_summaries(
+    conn: &mut PgConnection,
+    user_id: i32,
+    include_archived: bool,
+    search: Option<&str>,
+) -> GroovesResult<i64> {
+    let count = sqlx::query_scalar(&format!(
+        r#"SELECT COUNT(*) FROM playlist p JOIN playlist_member m ON m.playlist_id = p.id
+            WHERE {SUMMARY_CONDITIONS}"#
+    ))
+    .bind(user_id)
+    .bind(include_archived)
+    .bind(search)
+    .fetch_one(conn)
+    .await?;
+
+    Ok(count)
+}
+
+/// The id of an owner of the playlist `p`. `p.owner_id` is whoever created the playlist, who
+/// might have left it or stopped being an owner since, so they're only used if they still are
+/// or if the playlist has no owner members
+pub const CURRENT_OWNER: &str = r#"COALESCE((
+    SELECT o.user_id FROM playlist_member o
+    WHERE o.playlist_id = p.id AND o.role = 'owner'
+    ORDER BY o.user_id = p.owner_id DESC, o.user_id
+    LIMIT 1
+), p.owner_id)"#;
+
+pub async fn current_owner(conn: &mut PgConnection, playlist_id: i32) -> GroovesResult<i32> {
+    let owner_id = sqlx::query_scalar(&format!(
+        "SELECT {CURRENT_OWNER} FROM playlist p WHERE p.id = $1"
+    ))
+    .bind(playlist_id)
+    .fetch_optional(conn)
+    .await?
+    .ok_or(GroovesError::NotFound)?;
+
+    Ok(owner_id)
+}
+
+/// Playlists are returned with their current owner, so their version changes when the owner
+/// does. `previous_owner_id` is who it was before the members were changed
+pub async fn bump_version_if_owner_changed(
+    conn: &mut PgConnection,
+    playlist_id: i32,
+    previous_owner_id: i32,
+) -> GroovesResult<()> {
+    if current_owner(conn, playlist_id).await? != previous_owner_id {
+        bump_version(conn, playlist_id).await?;
+    }
+
+    Ok(())
+}
+
+/// Checks that the user is a member of the playlist with at least the given role
+///
+/// Non-members get NotFound so they can't tell which playlists exist
+pub async fn require_role(
+    conn: &mut PgConnection,
+    playlist_id: i32,
+    user_id: i32,
+    role: PlaylistRole,
+) -> GroovesResult<PlaylistRole> {
+    let member_role: PlaylistRole = sqlx::query_scalar(
+        "SELECT role FROM playlist_member WHERE playlist_id = $1 AND user_id = $2",
+    )
+    .bind(playlist_id)
+    .bind(user_id)
+    .fetch_optional(conn)
+    .await?
+    .ok_or(GroovesError::NotFound)?;
+
+    if member_role < role {
+        return Err(GroovesError::Forbidden);
+    }
+
+    Ok(member_role)
+}
+
+/// Locks the playlist until the transaction ends so concurrent changes happen one at a time
+pub async fn lock_playlist(
+    conn: &mut PgConnection,
+    playlist_id: i32,
+    user_id: i32,
+    role: PlaylistRole,
+) -> GroovesResult<Playlist> {
+    require_role(conn, playlist_id, user_id, role).await?;
+
+    let playlist = sqlx::query_as("SELECT * FROM playlist WHERE id = $1 FOR UPDATE")
+        .bind(playlist_id)
+        .fetch_optional(conn)
+        .await?
+        .ok_or(GroovesError::NotFound)?;
+
+    Ok(playlist)
+}
+
+/// Marks the playlist as changed, returning its new version
+pub async fn bump_version(conn: &mut PgConnection, playlist_id: i32) -> GroovesResult<i32> {
+    let version = sqlx::query_scalar(
+        "UPDATE playlist SET version = version + 1 WHERE id = $1 RETURNING version",
+    )
+    .bind(playlist_id)
+    .fetch_one(conn)
+    .await?;
+
+    Ok(version)
+}
+
+pub async fn create_playlist(
+    conn: &mut PgConnection,
+    name: String,
+    owner_id: i32,
+    elements: Vec<PlaylistElement>,
+) -> GroovesResult<Playlist> {
+    let mut playlist: Playlist =
+        sqlx::query_as("INSERT INTO playlist (name, owner_id) VALUES ($1, $2) RETURNING *")
+            .bind(name)
+            .bind(owner_id)
+            .fetch_one(&mut *conn)
+            .await?;
+
+    sqlx::query(
+        "INSERT INTO playlist_member (playlist_id, user_id, role) VALUES ($1, $2, 'owner')",
+    )
+    .bind(playlist.id)
+    .bind(owner_id)
+    .execute(&mut *conn)
+    .await?;
+
+    insert_elements(conn, playlist.id, 0, &elements).await?;
+    playlist.elements = elements;
+
+    record_revision(conn, &playlist, owner_id).await?;
+
+    Ok(playlist)
+}
+
+/// How many revisions are kept for each playlist. Older ones are deleted as new ones are saved
+const MAX_REVISIONS: i64 = 100;
+
+/// Snapshots the playlist as it's being saved so it can be restored later
+pub async fn record_revision(
+    conn: &mut PgConnection,
+    playlist: &Playlist,
+    author_id: i32,
+) -> GroovesResult<()> {
+    sqlx::query(
+        "INSERT INTO playlist_revision (playlist_id, author_id, name, elements) VALUES ($1, $2, $3, $4)",
+    )
+    .bind(playlist.id)
+    .bind(author_id)
+    .bind(&playlist.name)
+    .bind(sqlx::types::Json(&playlist.elements))
+    .execute(&mut *conn)
+    .await?;
+
+    sqlx::query(
+        r#"DELETE FROM playlist_revision
+            WHERE playlist_id = $1 AND id NOT IN (
+                SELECT id FROM playlist_revision WHERE playlist_id = $1 ORDER BY id DESC LIMIT $2
+            )"#,
+    )
+    .bind(playlist.id)
+    .bind(MAX_REVISIONS)
+    .execute(conn)
+    .await?;
+
+    Ok(())
+}
+
+/// Stores the elements of a playlist, replacing any it already had
+pub async fn replace_elements(
+    conn: &mut PgConnection,
+    playlist_id: i32,
+    elements: &[PlaylistElement],
+) -> GroovesResult<()> {
+    sqlx::query("DELETE FROM playlist_element WHERE playlist_id = $1")
+        .bind(playlist_id)
+        .execute(&mut *conn)
+        .await?;
+
+    insert_elements(conn, playlist_id, 0, elements).await
+}
+
+/// The albums the playlist's elements were made from
+pub async fn album_ids(
+    conn: &mut PgConnection,
+    playlist_id: i32,
+) -> GroovesResult<Vec<AlbumId<'static>>> {
+    let album_ids: Vec<String> = sqlx::query_scalar(
+        "SELECT album_id FROM playlist_element WHERE playlist_id = $1 AND album_id IS NOT NULL",
+    )
+    .bind(playlist_id)
+    .fetch_all(conn)
+    .await?;
+
+    Ok(album_ids
+        .into_iter()
+        .map(AlbumId::from_id)
+        .collect::<Result<_, _>>()?)
+}
+
+pub async fn count_elements(conn: &mut PgConnection, playlist_id: i32) -> GroovesResult<i32> {
+    let count: i64 =
+        sqlx::query_scalar("SELECT COUNT(*) FROM playlist_element WHERE playlist_id = $1")
+            .bind(playlist_id)
+            .fetch_one(conn)
+            .await?;
+
+    Ok(count as i32)
+}
+
+/// Inserts elements at `position`, moving everything from there onwards back to make room
+pub async fn insert_elements_at(
+    conn: &mut PgConnection,
+    playlist_id: i32,
+    position: i32,
+    elements: &[PlaylistElement],
+) -> GroovesResult<()> {
+    sqlx::query(
+        "UPDATE playlist_element SET position = position + $3 WHERE playlist_id = $1 AND position >= $2",
+    )
+    .bind(playlist_id)
+    .bind(position)
+    .bind(elements.len() as i32)
+    .execute(&mut *conn)
+    .await?;
+
+    insert_elements(conn, playlist_id, position, elements).await
+}
+
+/// Removes the element at `position`, moving everything after it forward.
+/// Returns false if there was no element there
+pub async fn remove_element(
+    conn: &mut PgConnection,
+    playlist_id: i32,
+    position: i32,
+) -> GroovesResult<bool> {
+    let res = sqlx::query("DELETE FROM playlist_element WHERE playlist_id = $1 AND position = $2")
+        .bind(playlist_id)
+        .bind(position)
+        .execute(&mut *conn)
+        .await?;
+
+    if res.rows_affected() == 0 {
+        return Ok(false);
+    }
+
+    sqlx::query(
+        "UPDATE playlist_element SET position = position - 1 WHERE playlist_id = $1 AND position > $2",
+    )
+    .bind(playlist_id)
+    .bind(position)
+    .execute(conn)
+    .await?;
+
+    Ok(true)
+}
+
+/// Moves the element at `from` to `to`, shifting the elements in between.
+/// Both positions must already hold elements
+pub async fn move_element(
+    conn: &mut PgConnection,
+    playlist_id: i32,
+    from: i32,
+    to: i32,
+) -> GroovesResult<()> {
+    // The position constraint is deferrable, so it's only checked once every row is updated
+    sqlx::query(
+        r#"UPDATE playlist_element
+            SET position = CASE
+                WHEN position = $2 THEN $3
+                WHEN $2 < $3 THEN position - 1
+                ELSE position + 1
+            END
+            WHERE playlist_id = $1 AND position BETWEEN LEAST($2, $3) AND GREATEST($2, $3)"#,
+    )
+    .bind(playlist_id)
+    .bind(from)
+    .bind(to)
+    .execute(conn)
+    .await?;
+
+    Ok(())
+}
+
+pub async fn count_songs(
+    conn: &mut PgConnection,
+    playlist_id: i32,
+    position: i32,
+) -> GroovesResult<i32> {
+    let count: i64 = sqlx::query_scalar(
+        r#"SELECT COUNT(*) FROM playlist_element_track t JOIN playlist_element e ON t.element_id = e.id
+            WHERE e.playlist_id = $1 AND e.position = $2"#,
+    )
+    .bind(playlist_id)
+    .bind(position)
+    .fetch_one(conn)
+    .await?;
+
+    Ok(count as i32)
+}
+
+/// Sets whether the songs at `songs` in the element at `position` are excluded from playback
+pub async fn set_excluded(
+    conn: &mut PgConnection,
+    playlist_id: i32,
+    position: i32,
+    songs: &[i32],
+    excluded: bool,
+) -> GroovesResult<()> {
+    sqlx::query(
+        r#"UPDATE playlist_element_track t SET excluded = $4
+            FROM playlist_element e
+            WHERE t.element_id = e.id AND e.playlist_id = $1 AND e.position = $2
+                AND t.position = ANY($3)"#,
+    )
+    .bind(playlist_id)
+    .bind(position)
+    .bind(songs)
+    .bind(excluded)
+    .execute(conn)
+    .await?;
+
+    Ok(())
+}
+
+/// Excludes every song of the element at `position` except the ones from `start` up to `end`
+pub async fn select_song_range(
+    conn: &mut PgConnection,
+    playlist_id: i32,
+    position: i32,
+    start: i32,
+    end: i32,
+) -> GroovesResult<()> {
+    sqlx::query(
+        r#"UPDATE playlist_element_track t SET excluded = NOT (t.position >= $3 AND t.position < $4)
+            FROM playlist_element e
+            WHERE t.element_id = e.id AND e.playlist_id = $1 AND e.position = $2"#,
+    )
+    .bind(playlist_id)
+    .bind(position)
+    .bind(start)
+    .bind(end)
+    .execute(conn)
+    .await?;
+
+    Ok(())
+}
+
+/// Whether the element at `position` has any songs that aren't excluded or unavailable
+pub async fn has_playable_songs(
+    conn: &mut PgConnection,
+    playlist_id: i32,
+    position: i32,
+) -> GroovesResult<bool> {
+    let included = sqlx::query_scalar(
+        r#"SELECT EXISTS (
+                SELECT 1 FROM playlist_element_track t JOIN playlist_element e ON t.element_id = e.id
+                WHERE e.playlist_id = $1 AND e.position = $2 AND NOT t.excluded AND NOT t.unavailable
+            )"#,
+    )
+    .bind(playlist_id)
+    .bind(position)
+    .fetch_one(conn)
+    .await?;
+
+    Ok(included)
+}
+
+/// Replaces the tags, note, rating and shuffle weight of the element at `position`.
+/// Returns false if there was no element there
+pub async fn set_annotations(
+    conn: &mut PgConnection,
+    playlist_id: i32,
+    position: i32,
+    annotations: &ElementAnnotations,
+) -> GroovesResult<bool> {
+    let res = sqlx::query(
+        r#"UPDATE playlist_element SET tags = $3, note = $4, rating = $5, shuffle_weight = $6
+            WHERE playlist_id = $1 AND position = $2"#,
+    )
+    .bind(playlist_id)
+    .bind(position)
+    .bind(sqlx::types::Json(&annotations.tags))
+    .bind(&annotations.note)
+    .bind(annotations.rating)
+    .bind(annotations.shuffle_weight)
+    .execute(conn)
+    .await?;
+
+    Ok(res.rows_affected() > 0)
+}
+
+/// The rules of a smart playlist, or None if it's an ordinary playlist
+pub async fn smart_rules(
+    conn: &mut PgConnection,
+    playlist_id: i32,
+) -> GroovesResult<Option<SmartRules>> {
+    let smart_playlist: Option<SmartPlaylist> =
+        sqlx::query_as("SELECT * FROM smart_playlist WHERE playlist_id = $1")
+            .bind(playlist_id)
+            .fetch_optional(conn)
+            .await?;
+
+    Ok(smart_playlist.map(|s| s.rules))
+}
+
+pub async fn insert_smart_rules(
+    conn: &mut PgConnection,
+    playlist_id: i32,
+    rules: &SmartRules,
+) -> GroovesResult<()> {
+    sqlx::query("INSERT INTO smart_playlist (playlist_id, rules) VALUES ($1, $2)")
+        .bind(playlist_id)
+        .bind(sqlx::types::Json(rules))
+        .execute(conn)
+        .await?;
+
+    Ok(())
+}
+
+/// Smart playlists' elements come from their rules, so they can't be edited directly
+pub async fn require_not_smart(conn: &mut PgConnection, playlist_id: i32) -> GroovesResult<()> {
+    if smart_rules(conn, playlist_id).await?.is_some() {
+        return Err(GroovesError::InvalidRequest);
+    }
+
+    Ok(())
+}
+
+/// Inserts elements at consecutive positions beginning at `start`.
+/// Those positions must not already be taken
+pub async fn insert_elements(
+    conn: &mut PgConnection,
+    playlist_id: i32,
+    start: i32,
+    elements: &[PlaylistElement],
+) -> GroovesResult<()> {
+    if elements.is_empty() {
+        return Ok(());
+    }
+
+    let positions: Vec<i32> = (start..).take(elements.len()).collect();
+    let names: Vec<&str> = elements.iter().map(|e| &*e.name).collect();
+    let image_urls: Vec<&str> = elements.iter().map(|e| &*e.image_url).collect();
+    let artists: Vec<&str> = elements.iter().map(|e| &*e.artists).collect();
+    let album_ids: Vec<Option<&str>> = elements
+        .iter()
+        .map(|e| e.album_id.as_ref().map(|id| id.id()))
+        .collect();
+    let release_dates: Vec<Option<&str>> =
+        elements.iter().map(|e| e.release_date.as_deref()).collect();
+    let labels: Vec<Option<&str>> = elements.iter().map(|e| e.label.as_deref()).collect();
+    // Arrays of arrays have to be rectangular in postgres, so these go in as json
+    let genres: Vec<String> = elements
+        .iter()
+        .map(|e| serde_json::to_string(&e.genres))
+        .collect::<Result<_, _>>()?;
+    let durations: Vec<Option<i32>> = elements.iter().map(|e| e.duration_ms).collect();
+    let tags: Vec<String> = elements
+        .iter()
+        .map(|e| serde_json::to_string(&e.tags))
+        .collect::<Result<_, _>>()?;
+    let notes: Vec<Option<&str>> = elements.iter().map(|e| e.note.as_deref()).collect();
+    let ratings: Vec<Option<i32>> = elements.iter().map(|e| e.rating).collect();
+    let shuffle_weights: Vec<Option<i32>> = elements.iter().map(|e| e.shuffle_weight).collect();
+
+    let inserted: Vec<(i32, i32)> = sqlx::query_as(
+        r#"INSERT INTO playlist_element
+                (playlist_id, position, name, image_url, artists,
+                album_id, release_date, label, genres, duration_ms,
+                tags, note, rating, shuffle_weight)
+            SELECT $1, position, name, image_url, artists,
+                album_id, release_date, label, genres::JSONB, duration_ms,
+                tags::JSONB, note, rating, shuffle_weight
+            FROM UNNEST($2::INT[], $3::TEXT[], $4::TEXT[], $5::TEXT[],
+                $6::TEXT[], $7::TEXT[], $8::TEXT[], $9::TEXT[], $10::INT[],
+                $11::TEXT[], $12::TEXT[], $13::INT[], $14::INT[])
+                AS e(position, name, image_url, artists,
+                album_id, release_date, label, genres, duration_ms,
+                tags, note, rating, shuffle_weight)
+            RETURNING position, id"#,
+    )
+    .bind(playlist_id)
+    .bind(&positions)
+    .bind(&names)
+    .bind(&image_urls)
+    .bind(&artists)
+    .bind(&album_ids)
+    .bind(&release_dates)
+    .bind(&labels)
+    .bind(&genres)
+    .bind(&durations)
+    .bind(&tags)
+    .bind(&notes)
+    .bind(&ratings)
+    .bind(&shuffle_weights)
+    .fetch_all(&mut *conn)
+    .await?;
+
+    let element_ids: HashMap<i32, i32> = inserted.into_iter().collect();
+
+    let mut song_element_ids = Vec::new();
+    let mut song_positions = Vec::new();
+    let mut song_names = Vec::new();
+    let mut song_image_urls = Vec::new();
+    let mut song_artists = Vec::new();
+    let mut song_spotify_ids = Vec::new();
+    let mut song_durations = Vec::new();
+    let mut song_track_numbers = Vec::new();
+    let mut song_disc_numbers = Vec::new();
+    let mut song_explicit = Vec::new();
+    let mut song_isrcs = Vec::new();
+    let mut song_artist_ids = Vec::new();
+    let mut song_unavailable = Vec::new();
+    let mut song_excluded = Vec::new();
+
+    for (position, element) in positions.iter().zip(elements) {
+        for (song_position, song) in (0..).zip(&element.songs) {
+            song_element_ids.push(element_ids[position]);
+            song_positions.push(song_position);
+            song_names.push(&*song.name);
+            song_image_urls.push(&*song.image_url);
+            song_artists.push(&*song.artists);
+            song_spotify_ids.push(song.spotify_id.id());
+            song_durations.push(song.duration_ms);
+            song_track_numbers.push(song.track_number);
+            song_disc_numbers.push(song.disc_number);
+            song_explicit.push(song.explicit);
+            song_isrcs.push(song.isrc.as_deref());
+            song_artist_ids.push(serde_json::to_string(&song.artist_ids)?);
+            song_unavailable.push(song.unavailable);
+            song_excluded.push(song.excluded);
+        }
+    }
+
+    sqlx::query(
+        r#"INSERT INTO playlist_element_track
+                (element_id, position, name, image_url, artists, spotify_id,
+                duration_ms, track_number, disc_number, explicit, isrc, artist_ids, unavailable,
+                excluded)
+            SELECT element_id, position, name, image_url, artists, spotify_id,
+                duration_ms, track_number, disc_number, explicit, isrc, artist_ids::JSONB,
+                unavailable, excluded
+            FROM UNNEST($1::INT[], $2::INT[], $3::TEXT[], $4::TEXT[], $5::TEXT[], $6::TEXT[],
+                $7::INT[], $8::INT[], $9::INT[], $10::BOOLEAN[], $11::TEXT[], $12::TEXT[],
+                $13::BOOLEAN[], $14::BOOLEAN[])
+                AS t(element_id, position, name, image_url, artists, spotify_id,
+                duration_ms, track_number, disc_number, explicit, isrc, artist_ids, unavailable,
+                excluded)"#,
+    )
+    .bind(&song_element_ids)
+    .bind(&song_positions)
+    .bind(&song_names)
+    .bind(&song_image_urls)
+    .bind(&song_artists)
+    .bind(&song_spotify_ids)
+    .bind(&song_durations)
+    .bind(&song_track_numbers)
+    .bind(&song_disc_numbers)
+    .bind(&song_explicit)
+    .bind(&song_isrcs)
+    .bind(&song_artist_ids)
+    .bind(&song_unavailable)
+    .bind(&song_excluded)
+    .execute(&mut *conn)
+    .await?;
+
+    Ok(())
+}
@@ -1,13 +1,28 @@
 use axum::http::StatusCode;
 use axum::response::IntoResponse;
+use axum::Json;
+use serde::Serialize;
+use serde_json::json;
 use tracing::debug;
 
+/// What's wrong with one field of a request body
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct FieldError {
+    /// Where the field is in the body, e.g. `elements[2].songs`
+    pub field: String,
+    pub message: String,
+}
+
 #[derive(Debug)]
 pub enum GroovesError {
     NotFound,
     Unauthorized,
     Forbidden,
     InvalidRequest,
+    /// The resource changed since the client last saw it
+    PreconditionFailed,
+    /// The request body had fields that are missing, malformed or out of bounds
+    Validation(Vec<FieldError>),
     InternalError(anyhow::Error),
 }
 
@@ -31,6 +46,12 @@ impl IntoResponse for GroovesError {
             Self::Forbidden => (StatusCode::FORBIDDEN).into_response(),
             Self::InvalidRequest => (StatusCode::BAD_REQUEST).into_response(),
             Self::NotFound => (StatusCode::NOT_FOUND).into_response(),
+            Self::PreconditionFailed => (StatusCode::PRECONDITION_FAILED).into_response(),
+            Self::Validation(errors) => (
+                StatusCode::UNPROCESSABLE_ENTITY,
+                Json(json!({ "errors": errors })),
+            )
+                .into_response(),
             Self::InternalError(error) => {
                 debug!(error_source = error.source(), "error source");
                 (StatusCode::INTERNAL_SERVER_ERROR, error.to_string()).into_response()
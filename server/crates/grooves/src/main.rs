@@ -1,4 +1,4 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::net::SocketAddr;
 use std::str::FromStr;
 use std::sync::{Arc, Mutex};
@@ -13,11 +13,15 @@ use tracing_subscriber::layer::SubscriberExt;
 use tracing_subscriber::util::SubscriberInitExt;
 use tracing_subscriber::EnvFilter;
 
+mod db;
 mod error;
+mod metadata;
 mod middleware;
 mod routes;
+mod smart;
 mod state;
 mod util;
+mod validation;
 
 type AppState = Arc<State>;
 
@@ -63,8 +67,13 @@ async fn main() {
         db_pool: pool,
         player_manager: PlayerManager::new(),
         sse_tokens: Mutex::new(HashMap::new()),
+        play_recorders: Mutex::new(HashSet::new()),
+        limits: validation::Limits::from_env(),
     });
 
+    tokio::spawn(metadata::backfill(state.clone()));
+    tokio::spawn(metadata::refresh_job(state.clone()));
+
     let router = routes::router(state.clone()).with_state(state);
 
     let port = std::env::var("GROOVES_PORT")
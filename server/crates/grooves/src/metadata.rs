@@ -0,0 +1,615 @@
+use std::collections::HashMap;
+use std::time::Duration;
+
+use grooves_model::{Playlist, PlaylistElement, Song, User};
+use itertools::Itertools;
+use rspotify::model::{AlbumId, FullAlbum, FullTrack, Market, TrackId};
+use rspotify::prelude::{BaseClient, Id};
+use rspotify::AuthCodeSpotify;
+use serde::Serialize;
+use sqlx::{PgConnection, PgPool};
+use tracing::{info, warn};
+
+use crate::db::playlists as db;
+use crate::error::{GroovesError, GroovesResult};
+use crate::util::spotify;
+use crate::AppState;
+
+/// The most albums spotify will return from a single request
+const ALBUMS_PER_REQUEST: usize = 20;
+
+/// The most tracks spotify will return from a single request
+const TRACKS_PER_REQUEST: usize = 50;
+
+/// Fills in the metadata of elements that were saved before it was stored. Each playlist
+/// owner's spotify token is used to look up their playlists
+pub async fn backfill(state: AppState) {
+    let owner_ids: Vec<i32> = match sqlx::query_scalar(&format!(
+        r#"SELECT DISTINCT {} FROM playlist_element e JOIN playlist p ON e.playlist_id = p.id
+            WHERE e.album_id IS NULL"#,
+        db::CURRENT_OWNER
+    ))
+    .fetch_all(&state.db_pool)
+    .await
+    {
+        Ok(owner_ids) => owner_ids,
+        Err(e) => {
+            warn!(error = ?e, "failed to find elements without metadata");
+            return;
+        }
+    };
+
+    for owner_id in owner_ids {
+        match backfill_owner(&state, owner_id).await {
+            Ok(updated) => info!(owner_id, updated, "backfilled element metadata"),
+            Err(e) => warn!(error = ?e, owner_id, "failed to backfill element metadata"),
+        }
+    }
+}
+
+async fn backfill_owner(state: &AppState, owner_id: i32) -> GroovesResult<usize> {
+    let mut conn = state.db_pool.acquire().await?;
+
+    let user: User = sqlx::query_as(r#"SELECT * FROM "user" WHERE id = $1"#)
+        .bind(owner_id)
+        .fetch_one(&mut *conn)
+        .await?;
+    let Some(token) = user.token else {
+        return Ok(0);
+    };
+
+    let element_ids: Vec<i32> = sqlx::query_scalar(&format!(
+        r#"SELECT e.id FROM playlist_element e JOIN playlist p ON e.playlist_id = p.id
+            WHERE e.album_id IS NULL AND {} = $1"#,
+        db::CURRENT_OWNER
+    ))
+    .bind(owner_id)
+    .fetch_all(&mut *conn)
+    .await?;
+
+    let client = spotify::client_with_token(token.clone());
+    let updated = update_metadata(&mut conn, &client, &element_ids).await?;
+    spotify::save_refreshed_token(&client, token, owner_id, &state.db_pool).await?;
+
+    Ok(updated)
+}
+
+/// Looks up the elements' songs and albums on spotify and stores their metadata.
+/// Each element's album is the album of its first song. Returns how many elements were updated
+pub async fn update_metadata(
+    conn: &mut PgConnection,
+    client: &AuthCodeSpotify,
+    element_ids: &[i32],
+) -> GroovesResult<usize> {
+    let songs: Vec<(i32, String)> = sqlx::query_as(
+        r#"SELECT element_id, spotify_id FROM playlist_element_track
+            WHERE element_id = ANY($1)
+            ORDER BY element_id, position"#,
+    )
+    .bind(element_ids)
+    .fetch_all(&mut *conn)
+    .await?;
+
+    let track_ids: Vec<TrackId<'static>> = songs
+        .iter()
+        .map(|(_, id)| &**id)
+        .unique()
+        .filter_map(|id| TrackId::from_id(id.to_owned()).ok())
+        .collect();
+
+    let tracks: HashMap<String, FullTrack> = spotify::fetch_tracks(client, &track_ids)
+        .await?
+        .into_iter()
+        .filter_map(|t| Some((t.id.as_ref()?.id().to_owned(), t)))
+        .collect();
+
+    update_song_metadata(conn, element_ids, tracks.values()).await?;
+
+    let element_albums: Vec<(i32, AlbumId<'static>)> = songs
+        .iter()
+        .unique_by(|(element_id, _)| element_id)
+        .filter_map(|(element_id, spotify_id)| {
+            Some((*element_id, tracks.get(spotify_id)?.album.id.clone()?))
+        })
+        .collect();
+
+    let album_ids: Vec<AlbumId<'static>> = element_albums
+        .iter()
+        .map(|(_, id)| id.clone())
+        .unique()
+        .collect();
+
+    let albums = fetch_albums(client, &album_ids).await?;
+
+    let element_albums: Vec<(i32, &FullAlbum)> = element_albums
+        .iter()
+        .filter_map(|(element_id, album_id)| Some((*element_id, albums.get(album_id)?)))
+        .collect();
+
+    update_element_metadata(conn, &element_albums).await?;
+
+    Ok(element_albums.len())
+}
+
+async fn update_song_metadata(
+    conn: &mut PgConnection,
+    element_ids: &[i32],
+    tracks: impl Iterator<Item = &FullTrack>,
+) -> GroovesResult<()> {
+    let mut spotify_ids = Vec::new();
+    let mut durations = Vec::new();
+    let mut track_numbers = Vec::new();
+    let mut disc_numbers = Vec::new();
+    let mut explicit = Vec::new();
+    let mut isrcs = Vec::new();
+    let mut artist_ids = Vec::new();
+
+    for track in tracks {
+        let Some(id) = &track.id else {
+            continue;
+        };
+        let track_artist_ids: Vec<&str> = track
+            .artists
+            .iter()
+            .filter_map(|a| Some(a.id.as_ref()?.id()))
+            .collect();
+
+        spotify_ids.push(id.id());
+        durations.push(track.duration.num_milliseconds() as i32);
+        track_numbers.push(track.track_number as i32);
+        disc_numbers.push(track.disc_number);
+        explicit.push(track.explicit);
+        isrcs.push(track.external_ids.get("isrc").map(|s| &**s));
+        artist_ids.push(serde_json::to_string(&track_artist_ids)?);
+    }
+
+    sqlx::query(
+        r#"UPDATE playlist_element_track t
+            SET duration_ms = u.duration_ms, track_number = u.track_number,
+                disc_number = u.disc_number, explicit = u.explicit, isrc = u.isrc,
+                artist_ids = u.artist_ids::JSONB
+            FROM UNNEST($2::TEXT[], $3::INT[], $4::INT[], $5::INT[], $6::BOOLEAN[], $7::TEXT[],
+                $8::TEXT[])
+                AS u(spotify_id, duration_ms, track_number, disc_number, explicit, isrc, artist_ids)
+            WHERE t.element_id = ANY($1) AND t.spotify_id = u.spotify_id"#,
+    )
+    .bind(element_ids)
+    .bind(&spotify_ids)
+    .bind(&durations)
+    .bind(&track_numbers)
+    .bind(&disc_numbers)
+    .bind(&explicit)
+    .bind(&isrcs)
+    .bind(&artist_ids)
+    .execute(conn)
+    .await?;
+
+    Ok(())
+}
+
+/// The duration is the total of the element's songs, so their metadata has to be stored first
+async fn update_element_metadata(
+    conn: &mut PgConnection,
+    element_albums: &[(i32, &FullAlbum)],
+) -> GroovesResult<()> {
+    let element_ids: Vec<i32> = element_albums.iter().map(|(id, _)| *id).collect();
+    let album_ids: Vec<&str> = element_albums.iter().map(|(_, a)| a.id.id()).collect();
+    let release_dates: Vec<&str> = element_albums
+        .iter()
+        .map(|(_, a)| &*a.release_date)
+        .collect();
+    let labels: Vec<Option<&str>> = element_albums
+        .iter()
+        .map(|(_, a)| a.label.as_deref())
+        .collect();
+    let genres: Vec<String> = element_albums
+        .iter()
+        .map(|(_, a)| serde_json::to_string(&a.genres))
+        .collect::<Result<_, _>>()?;
+
+    sqlx::query(
+        r#"UPDATE playlist_element e
+            SET album_id = u.album_id, release_date = u.release_date, label = u.label,
+                genres = u.genres::JSONB,
+                duration_ms = (
+                    SELECT SUM(t.duration_ms) FROM playlist_element_track t
+                    WHERE t.element_id = e.id
+                )
+            FROM UNNEST($1::INT[], $2::TEXT[], $3::TEXT[], $4::TEXT[], $5::TEXT[])
+                AS u(id, album_id, release_date, label, genres)
+            WHERE e.id = u.id"#,
+    )
+    .bind(&element_ids)
+    .bind(&album_ids)
+    .bind(&release_dates)
+    .bind(&labels)
+    .bind(&genres)
+    .execute(conn)
+    .await?;
+
+    Ok(())
+}
+
+/// How often the refresh job looks for playlists to refresh
+const REFRESH_CHECK_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+/// Playlists are refreshed by the job once they haven't been for this long
+const REFRESH_AGE: &str = "7 days";
+
+/// Playlists the job failed to refresh, e.g. because their owner's token was revoked, are only
+/// tried again after this long, so they don't keep the others from being refreshed
+const REFRESH_RETRY_AGE: &str = "1 day";
+
+/// The most playlists the job refreshes each time it runs, to stay under spotify's rate limit
+const REFRESH_BATCH_SIZE: i64 = 20;
+
+#[derive(Clone, Debug, Serialize)]
+pub struct RefreshReport {
+    pub playlist_id: i32,
+    pub version: i32,
+    pub elements: Vec<ElementRefresh>,
+    pub songs: Vec<SongRefresh>,
+}
+
+/// The album metadata fields of an element that were changed
+#[derive(Clone, Debug, Serialize)]
+pub struct ElementRefresh {
+    pub position: usize,
+    pub name: String,
+    pub fields: Vec<&'static str>,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct SongRefresh {
+    pub element_position: usize,
+    pub song_position: usize,
+    pub name: String,
+    pub changes: Vec<SongChange>,
+}
+
+#[derive(Clone, Debug, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum SongChange {
+    /// Spotify replaced the track with another copy of it, e.g. from a remaster
+    Relinked {
+        from: String,
+    },
+    Renamed {
+        from: String,
+    },
+    Unavailable,
+    Available,
+}
+
+/// Periodically refreshes the playlists that haven't been refreshed for a while, using their
+/// owners' spotify tokens
+pub async fn refresh_job(state: AppState) {
+    let mut interval = tokio::time::interval(REFRESH_CHECK_INTERVAL);
+
+    loop {
+        interval.tick().await;
+
+        let playlists: Vec<(i32, i32)> = match sqlx::query_as(&format!(
+            r#"SELECT p.id, {} FROM playlist p
+                WHERE (p.refreshed_at IS NULL OR p.refreshed_at < now() - $1::INTERVAL)
+                    AND (p.refresh_failed_at IS NULL OR p.refresh_failed_at < now() - $2::INTERVAL)
+                ORDER BY GREATEST(p.refreshed_at, p.refresh_failed_at) NULLS FIRST
+                LIMIT $3"#,
+            db::CURRENT_OWNER
+        ))
+        .bind(REFRESH_AGE)
+        .bind(REFRESH_RETRY_AGE)
+        .bind(REFRESH_BATCH_SIZE)
+        .fetch_all(&state.db_pool)
+        .await
+        {
+            Ok(playlists) => playlists,
+            Err(e) => {
+                warn!(error = ?e, "failed to find playlists to refresh");
+                continue;
+            }
+        };
+
+        for (playlist_id, owner_id) in playlists {
+            match refresh_owned_playlist(&state, playlist_id, owner_id).await {
+                Ok(report) => info!(
+                    playlist_id,
+                    elements = report.elements.len(),
+                    songs = report.songs.len(),
+                    "refreshed playlist"
+                ),
+                Err(e) => {
+                    warn!(error = ?e, playlist_id, "failed to refresh playlist");
+
+                    if let Err(e) =
+                        sqlx::query("UPDATE playlist SET refresh_failed_at = now() WHERE id = $1")
+                            .bind(playlist_id)
+                            .execute(&state.db_pool)
+                            .await
+                    {
+                        warn!(error = ?e, playlist_id, "failed to record refresh failure");
+                    }
+                }
+            }
+        }
+    }
+}
+
+async fn refresh_owned_playlist(
+    state: &AppState,
+    playlist_id: i32,
+    owner_id: i32,
+) -> GroovesResult<RefreshReport> {
+    let user: User = sqlx::query_as(r#"SELECT * FROM "user" WHERE id = $1"#)
+        .bind(owner_id)
+        .fetch_one(&state.db_pool)
+        .await?;
+    let token = user.token.ok_or(GroovesError::Unauthorized)?;
+
+    let client = spotify::client_with_token(token.clone());
+    let report = refresh_playlist(&state.db_pool, &client, playlist_id, owner_id).await?;
+    spotify::save_refreshed_token(&client, token, owner_id, &state.db_pool).await?;
+
+    Ok(report)
+}
+
+/// Re-fetches the albums and songs of a playlist and saves whatever changed as a new revision
+/// by `author_id`. Fails with PreconditionFailed if the playlist is changed in the meantime
+pub async fn refresh_playlist(
+    db_pool: &PgPool,
+    client: &AuthCodeSpotify,
+    playlist_id: i32,
+    author_id: i32,
+) -> GroovesResult<RefreshReport> {
+    let mut conn = db_pool.acquire().await?;
+    let mut playlist: Playlist = sqlx::query_as("SELECT * FROM playlist WHERE id = $1")
+        .bind(playlist_id)
+        .fetch_optional(&mut *conn)
+        .await?
+        .ok_or(GroovesError::NotFound)?;
+    db::load_elements(&mut conn, std::slice::from_mut(&mut playlist)).await?;
+    drop(conn);
+
+    // Spotify is slow, so the playlist is only locked once the new metadata is ready
+    let original_elements = playlist.elements.clone();
+    let (elements, songs) = refresh_elements(client, &mut playlist.elements).await?;
+    let changed = playlist.elements != original_elements;
+
+    let mut tx = db_pool.begin().await?;
+    let locked: Playlist = sqlx::query_as("SELECT * FROM playlist WHERE id = $1 FOR UPDATE")
+        .bind(playlist_id)
+        .fetch_optional(&mut *tx)
+        .await?
+        .ok_or(GroovesError::NotFound)?;
+    if locked.version != playlist.version {
+        return Err(GroovesError::PreconditionFailed);
+    }
+
+    let mut report = RefreshReport {
+        playlist_id,
+        version: playlist.version,
+        elements,
+        songs,
+    };
+
+    // Changes that aren't reported, like durations, are still saved
+    if changed {
+        db::replace_elements(&mut tx, playlist_id, &playlist.elements).await?;
+    }
+
+    let bump = i32::from(changed);
+    report.version = sqlx::query_scalar(
+        r#"UPDATE playlist SET refreshed_at = now(), version = version + $2
+            WHERE id = $1
+            RETURNING version"#,
+    )
+    .bind(playlist_id)
+    .bind(bump)
+    .fetch_one(&mut *tx)
+    .await?;
+
+    if changed {
+        playlist.version = report.version;
+        db::record_revision(&mut tx, &playlist, author_id).await?;
+    }
+    tx.commit().await?;
+
+    Ok(report)
+}
+
+async fn refresh_elements(
+    client: &AuthCodeSpotify,
+    elements: &mut [PlaylistElement],
+) -> GroovesResult<(Vec<ElementRefresh>, Vec<SongRefresh>)> {
+    let album_ids: Vec<AlbumId<'static>> = elements
+        .iter()
+        .filter_map(|e| e.album_id.clone())
+        .unique()
+        .collect();
+    let albums = fetch_albums(client, &album_ids).await?;
+
+    let track_ids: Vec<TrackId<'static>> = elements
+        .iter()
+        .flat_map(|e| &e.songs)
+        .map(|s| s.spotify_id.clone())
+        .unique()
+        .collect();
+    let tracks = fetch_playable_tracks(client, &track_ids).await?;
+
+    let mut element_refreshes = Vec::new();
+    let mut song_refreshes = Vec::new();
+
+    for (position, element) in elements.iter_mut().enumerate() {
+        let album = element.album_id.as_ref().and_then(|id| albums.get(id));
+        if let Some(album) = album {
+            let fields = refresh_element(element, PlaylistElement::from(album.clone()));
+            if !fields.is_empty() {
+                element_refreshes.push(ElementRefresh {
+                    position,
+                    name: element.name.clone(),
+                    fields,
+                });
+            }
+        }
+
+        for (song_position, song) in element.songs.iter_mut().enumerate() {
+            let track = tracks.get(&song.spotify_id).and_then(Option::as_ref);
+            let changes = refresh_song(song, track);
+            if !changes.is_empty() {
+                song_refreshes.push(SongRefresh {
+                    element_position: position,
+                    song_position,
+                    name: song.name.clone(),
+                    changes,
+                });
+            }
+        }
+
+        element.duration_ms = Some(element.songs.iter().filter_map(|s| s.duration_ms).sum());
+    }
+
+    Ok((element_refreshes, song_refreshes))
+}
+
+/// Copies the album metadata from `fresh`, returning the names of the fields that changed
+fn refresh_element(element: &mut PlaylistElement, fresh: PlaylistElement) -> Vec<&'static str> {
+    let mut fields = Vec::new();
+
+    if element.name != fresh.name {
+        element.name = fresh.name;
+        fields.push("name");
+    }
+    if element.image_url != fresh.image_url {
+        for song in &mut element.songs {
+            song.image_url.clone_from(&fresh.image_url);
+        }
+        element.image_url = fresh.image_url;
+        fields.push("image_url");
+    }
+    if element.artists != fresh.artists {
+        element.artists = fresh.artists;
+        fields.push("artists");
+    }
+    if element.release_date != fresh.release_date {
+        element.release_date = fresh.release_date;
+        fields.push("release_date");
+    }
+    if element.label != fresh.label {
+        element.label = fresh.label;
+        fields.push("label");
+    }
+    if element.genres != fresh.genres {
+        element.genres = fresh.genres;
+        fields.push("genres");
+    }
+
+    fields
+}
+
+/// `track` is None if spotify doesn't have the song anymore
+fn refresh_song(song: &mut Song, track: Option<&FullTrack>) -> Vec<SongChange> {
+    let mut changes = Vec::new();
+
+    let playable = match track {
+        Some(track) => {
+            if let Some(id) = track.id.as_ref().filter(|&id| *id != song.spotify_id) {
+                changes.push(SongChange::Relinked {
+                    from: song.spotify_id.id().to_owned(),
+                });
+                song.spotify_id = id.clone();
+            }
+            if track.name != song.name {
+                changes.push(SongChange::Renamed {
+                    from: std::mem::replace(&mut song.name, track.name.clone()),
+                });
+            }
+
+            song.duration_ms = Some(track.duration.num_milliseconds() as i32);
+            song.track_number = Some(track.track_number as i32);
+            song.disc_number = Some(track.disc_number);
+            song.explicit = track.explicit;
+            song.isrc = track.external_ids.get("isrc").cloned().or(song.isrc.take());
+            song.artist_ids = track.artists.iter().filter_map(|a| a.id.clone()).collect();
+
+            track.is_playable != Some(false)
+        }
+        None => false,
+    };
+
+    if song.unavailable && playable {
+        changes.push(SongChange::Available);
+    } else if !song.unavailable && !playable {
+        changes.push(SongChange::Unavailable);
+    }
+    song.unavailable = !playable;
+
+    changes
+}
+
+/// Albums that spotify doesn't have anymore are left out. Any other failure fails the whole
+/// refresh, so an album isn't mistaken for gone just because spotify was unreachable
+pub async fn fetch_albums(
+    client: &AuthCodeSpotify,
+    album_ids: &[AlbumId<'static>],
+) -> GroovesResult<HashMap<AlbumId<'static>, FullAlbum>> {
+    let mut albums = HashMap::new();
+
+    for chunk in album_ids.chunks(ALBUMS_PER_REQUEST) {
+        match client.albums(chunk.iter().cloned(), None).await {
+            Ok(chunk_albums) => albums.extend(chunk_albums.into_iter().map(|a| (a.id.clone(), a))),
+            // A missing album fails the whole request, so find out which one it was
+            Err(_) => {
+                for album_id in chunk {
+                    match client.album(album_id.clone(), None).await {
+                        Ok(album) => {
+                            albums.insert(album_id.clone(), album);
+                        }
+                        Err(e) if spotify::is_not_found(&e) => {}
+                        Err(e) => return Err(e.into()),
+                    }
+                }
+            }
+        }
+    }
+
+    Ok(albums)
+}
+
+/// Looks up tracks as they are in the user's market, where spotify relinks tracks it has
+/// replaced and marks the ones that can't be played. Tracks spotify doesn't have are None
+async fn fetch_playable_tracks(
+    client: &AuthCodeSpotify,
+    track_ids: &[TrackId<'static>],
+) -> GroovesResult<HashMap<TrackId<'static>, Option<FullTrack>>> {
+    let mut tracks = HashMap::new();
+
+    for chunk in track_ids.chunks(TRACKS_PER_REQUEST) {
+        match client
+            .tracks(chunk.iter().cloned(), Some(Market::FromToken))
+            .await
+        {
+            // Tracks are returned in the order they were asked for, even relinked ones
+            Ok(chunk_tracks) => tracks.extend(
+                chunk
+                    .iter()
+                    .cloned()
+                    .zip(chunk_tracks.into_iter().map(Some)),
+            ),
+            Err(_) => {
+                for track_id in chunk {
+                    let track = match client
+                        .track(track_id.clone(), Some(Market::FromToken))
+                        .await
+                    {
+                        Ok(track) => Some(track),
+                        Err(e) if spotify::is_not_found(&e) => None,
+                        Err(e) => return Err(e.into()),
+                    };
+                    tracks.insert(track_id.clone(), track);
+                }
+            }
+        }
+    }
+
+    Ok(tracks)
+}
@@ -1,4 +1,4 @@
-use axum::http::header::AUTHORIZATION;
+use axum::http::header::{AUTHORIZATION, ETAG};
 use axum::http::{HeaderValue, Method};
 use axum::routing::get;
 use axum::Router;
@@ -8,8 +8,12 @@ use tower_http::trace::TraceLayer;
 use crate::AppState;
 
 mod auth;
+mod folders;
+mod invites;
 pub mod player;
 mod playlists;
+mod settings;
+mod shared;
 mod spotify;
 
 pub fn router(state: AppState) -> Router<AppState> {
@@ -25,6 +29,7 @@ pub fn router(state: AppState) -> Router<AppState> {
         ])
         .allow_headers([AUTHORIZATION])
         .allow_headers(tower_http::cors::Any)
+        .expose_headers([ETAG])
         .allow_origin(
             frontend_url
                 .parse::<HeaderValue>()
@@ -34,8 +39,12 @@ pub fn router(state: AppState) -> Router<AppState> {
     Router::<AppState>::new()
         .route("/", get(|| async { "Hello, World!" }))
         .nest("/auth", auth::router())
+        .nest("/folders", folders::router(state.clone()))
+        .nest("/invites", invites::router(state.clone()))
         .nest("/player", player::router(state.clone()))
         .nest("/playlists", playlists::router(state.clone()))
+        .nest("/settings", settings::router(state.clone()))
+        .nest("/shared", shared::router(state.clone()))
         .nest("/spotify", spotify::router(state))
         .layer(cors)
         .layer(TraceLayer::new_for_http())
@@ -0,0 +1,141 @@
+use axum::extract::{Path, State};
+use axum::http::StatusCode;
+use axum::response::IntoResponse;
+use axum::routing::{post, put};
+use axum::{Extension, Json, Router};
+use grooves_model::{PlaylistFolder, User};
+use serde::Deserialize;
+use sqlx::PgConnection;
+use tracing::info;
+
+use crate::db::folders as db;
+use crate::error::{GroovesError, GroovesResult};
+use crate::validation::{JsonBody, Limits, Validator};
+use crate::{middleware, AppState};
+
+pub fn router(state: AppState) -> Router<AppState> {
+    info!("Creating folder routes");
+
+    Router::new()
+        .route("/", post(create_folder))
+        .route("/:folderId", put(update_folder).delete(delete_folder))
+        .route_layer(axum::middleware::from_fn_with_state(
+            state,
+            middleware::auth::auth,
+        ))
+}
+
+#[derive(Deserialize, Clone, Debug)]
+struct FolderPayload {
+    name: String,
+    /// None to put the folder at the top level
+    parent_id: Option<i32>,
+    #[serde(default)]
+    position: i32,
+}
+
+async fn create_folder(
+    State(state): State<AppState>,
+    Extension(current_user): Extension<User>,
+    JsonBody(payload): JsonBody<FolderPayload>,
+) -> GroovesResult<impl IntoResponse> {
+    validate_name(&payload.name, &state.limits)?;
+
+    let mut conn = state.db_pool.acquire().await?;
+    if let Some(parent_id) = payload.parent_id {
+        db::require_folder(&mut conn, parent_id, current_user.id).await?;
+    }
+
+    let folder: PlaylistFolder = sqlx::query_as(
+        r#"INSERT INTO playlist_folder (user_id, parent_id, name, position)
+            VALUES ($1, $2, $3, $4)
+            RETURNING *"#,
+    )
+    .bind(current_user.id)
+    .bind(payload.parent_id)
+    .bind(payload.name)
+    .bind(payload.position)
+    .fetch_one(&mut *conn)
+    .await?;
+
+    Ok(Json(folder))
+}
+
+/// Renames, moves or reorders a folder. A folder can't be moved inside itself
+async fn update_folder(
+    State(state): State<AppState>,
+    Extension(current_user): Extension<User>,
+    Path(folder_id): Path<i32>,
+    JsonBody(payload): JsonBody<FolderPayload>,
+) -> GroovesResult<impl IntoResponse> {
+    validate_name(&payload.name, &state.limits)?;
+
+    let mut tx = state.db_pool.begin().await?;
+    db::require_folder(&mut tx, folder_id, current_user.id).await?;
+
+    if let Some(parent_id) = payload.parent_id {
+        db::lock_folders(&mut tx, current_user.id).await?;
+        db::require_folder(&mut tx, parent_id, current_user.id).await?;
+
+        if db::is_within(&mut tx, parent_id, folder_id).await? {
+            return Err(GroovesError::InvalidRequest);
+        }
+    }
+
+    let folder: PlaylistFolder = sqlx::query_as(
+        r#"UPDATE playlist_folder SET parent_id = $2, name = $3, position = $4
+            WHERE id = $1
+            RETURNING *"#,
+    )
+    .bind(folder_id)
+    .bind(payload.parent_id)
+    .bind(payload.name)
+    .bind(payload.position)
+    .fetch_one(&mut *tx)
+    .await?;
+    tx.commit().await?;
+
+    Ok(Json(folder))
+}
+
+fn validate_name(name: &str, limits: &Limits) -> GroovesResult<()> {
+    let mut validator = Validator::default();
+    validator.name("name", name, limits);
+    validator.finish()
+}
+
+/// Deletes a folder, moving everything in it to the folder it was in
+async fn delete_folder(
+    State(state): State<AppState>,
+    Extension(current_user): Extension<User>,
+    Path(folder_id): Path<i32>,
+) -> GroovesResult<impl IntoResponse> {
+    let mut tx = state.db_pool.begin().await?;
+    let folder = db::require_folder(&mut tx, folder_id, current_user.id).await?;
+
+    move_contents(&mut tx, &folder).await?;
+
+    sqlx::query("DELETE FROM playlist_folder WHERE id = $1")
+        .bind(folder_id)
+        .execute(&mut *tx)
+        .await?;
+    tx.commit().await?;
+
+    Ok(StatusCode::OK)
+}
+
+async fn move_contents(conn: &mut PgConnection, folder: &PlaylistFolder) -> GroovesResult<()> {
+    sqlx::query("UPDATE playlist_folder SET parent_id = $2 WHERE parent_id = $1")
+        .bind(folder.id)
+        .bind(folder.parent_id)
+        .execute(&mut *conn)
+        .await?;
+
+    sqlx::query("UPDATE playlist_member SET folder_id = $2 WHERE folder_id = $1")
+        .bind(folder.id)
+        .bind(folder.parent_id)
+        .execute(&mut *conn)
+        .await?;
+
+    Ok(())
+}
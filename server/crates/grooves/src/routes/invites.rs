@@ -0,0 +1,53 @@
+use axum::extract::{Path, State};
+use axum::response::IntoResponse;
+use axum::routing::post;
+use axum::{Extension, Json, Router};
+use grooves_model::{PlaylistInvite, User};
+use tracing::info;
+
+use crate::db::playlists as db;
+use crate::error::{GroovesError, GroovesResult};
+use crate::{middleware, AppState};
+
+pub fn router(state: AppState) -> Router<AppState> {
+    info!("Creating invite routes");
+
+    Router::new()
+        .route("/:token/accept", post(accept_invite))
+        .route_layer(axum::middleware::from_fn_with_state(
+            state,
+            middleware::auth::auth,
+        ))
+}
+
+/// Makes the current user a member of the invite's playlist. Accepting never lowers the role
+/// of someone who is already a member
+async fn accept_invite(
+    State(state): State<AppState>,
+    Extension(current_user): Extension<User>,
+    Path(token): Path<String>,
+) -> GroovesResult<impl IntoResponse> {
+    let mut tx = state.db_pool.begin().await?;
+
+    let invite: PlaylistInvite = sqlx::query_as("SELECT * FROM playlist_invite WHERE token = $1")
+        .bind(token)
+        .fetch_optional(&mut *tx)
+        .await?
+        .ok_or(GroovesError::NotFound)?;
+    let owner_id = db::current_owner(&mut tx, invite.playlist_id).await?;
+
+    sqlx::query(
+        r#"INSERT INTO playlist_member (playlist_id, user_id, role) VALUES ($1, $2, $3)
+            ON CONFLICT (playlist_id, user_id)
+            DO UPDATE SET role = GREATEST(playlist_member.role, EXCLUDED.role)"#,
+    )
+    .bind(invite.playlist_id)
+    .bind(current_user.id)
+    .bind(invite.role)
+    .execute(&mut *tx)
+    .await?;
+    db::bump_version_if_owner_changed(&mut tx, invite.playlist_id, owner_id).await?;
+    tx.commit().await?;
+
+    Ok(Json(invite))
+}
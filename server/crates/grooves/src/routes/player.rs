@@ -2,20 +2,27 @@ use std::collections::HashMap;
 use std::convert::Infallible;
 
 use anyhow::anyhow;
-use axum::extract::{Query, State};
+use axum::extract::{Path, Query, State};
 use axum::response::sse::{Event, KeepAlive};
 use axum::response::{IntoResponse, Sse};
 use axum::routing::{get, post};
 use axum::{Extension, Json, Router};
 use axum_macros::debug_handler;
-use grooves_model::{Playlist, User};
+use grooves_model::{
+    ElementFilter, ExplicitFilter, Playlist, PlaylistElement, PlaylistRole, ResumePoint, User,
+};
 use grooves_player::player::commands::Command as PlayerCommand;
+use grooves_player::player::validate_play;
+use rspotify::model::AlbumId;
 use serde::{Deserialize, Serialize};
 use tokio_stream::Stream;
-use tracing::info;
+use tracing::{info, warn};
 
+use crate::db::playlists as db;
 use crate::error::{GroovesError, GroovesResult};
-use crate::{middleware, util, AppState};
+use crate::util::spotify;
+use crate::validation::{validate_playlist, Validator};
+use crate::{middleware, smart, util, AppState};
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
 #[serde(tag = "type", rename_all = "snake_case")]
@@ -24,6 +31,35 @@ pub enum Command {
         playlist_id: i32,
         element_index: Option<usize>,
         song_index: Option<usize>,
+        seed: Option<u32>,
+        /// Continue from where the user last left off in the playlist
+        #[serde(default)]
+        resume: bool,
+        /// Only play the matching elements. `element_index` is then an index into those, and
+        /// the play isn't resumed or saved as a resume point
+        #[serde(default)]
+        filter: ElementFilter,
+        /// Overrides the user's explicit filter for this play
+        #[serde(default)]
+        explicit_filter: Option<ExplicitFilter>,
+    },
+    /// Play elements that aren't part of a saved playlist
+    PlayElements {
+        elements: Vec<PlaylistElement>,
+        element_index: Option<usize>,
+        song_index: Option<usize>,
+        seed: Option<u32>,
+        #[serde(default)]
+        explicit_filter: Option<ExplicitFilter>,
+    },
+    /// Play spotify albums that aren't part of a saved playlist
+    PlayAlbums {
+        album_ids: Vec<String>,
+        element_index: Option<usize>,
+        song_index: Option<usize>,
+        seed: Option<u32>,
+        #[serde(default)]
+        explicit_filter: Option<ExplicitFilter>,
     },
     Pause,
     Resume,
@@ -31,6 +67,11 @@ pub enum Command {
     PrevSong,
     NextElement,
     PrevElement,
+    SetRadio {
+        enabled: bool,
+    },
+    KeepGenerated,
+    DiscardGenerated,
     AddToQueue,
     RemoveFromQueue,
     Exit,
@@ -51,6 +92,20 @@ pub fn router(state: AppState) -> Router<AppState> {
         .route(
             "/sse_token",
             get(sse_token).route_layer(axum::middleware::from_fn_with_state(
+                state.clone(),
+                middleware::auth::auth,
+            )),
+        )
+        .route(
+            "/save",
+            post(save_playlist).route_layer(axum::middleware::from_fn_with_state(
+                state.clone(),
+                middleware::auth::auth,
+            )),
+        )
+        .route(
+            "/resume/:playlistId",
+            get(get_resume_point).route_layer(axum::middleware::from_fn_with_state(
                 state,
                 middleware::auth::auth,
             )),
@@ -121,20 +176,136 @@ pub async fn command_handler(
             playlist_id,
             element_index,
             song_index,
+            seed,
+            resume,
+            filter,
+            explicit_filter,
+        } => {
+            let mut conn = state.db_pool.acquire().await?;
+
+            // Any member can play a playlist, not just its owner
+            db::require_role(
+                &mut conn,
+                playlist_id,
+                current_user.id,
+                PlaylistRole::Viewer,
+            )
+            .await?;
+
+            let mut playlist: Playlist = sqlx::query_as("SELECT * FROM playlist WHERE id = $1")
+                .bind(playlist_id)
+                .fetch_optional(&mut *conn)
+                .await?
+                .ok_or(GroovesError::NotFound)?;
+            // Whoever created the playlist might not own it anymore
+            playlist.owner_id = db::current_owner(&mut conn, playlist_id).await?;
+
+            drop(conn);
+
+            let is_smart =
+                smart::load_elements(&state.db_pool, &current_user, &mut playlist).await?;
+            let explicit_filter = explicit_filter.unwrap_or(current_user.explicit_filter);
+
+            if filter.is_empty() && !is_smart {
+                let resume_point = if resume {
+                    fetch_resume_point(&state, current_user.id, playlist_id).await?
+                } else {
+                    None
+                };
+
+                play_command(
+                    current_user.id,
+                    playlist,
+                    element_index,
+                    song_index,
+                    seed,
+                    resume_point,
+                    explicit_filter,
+                )?
+            } else {
+                // Ephemeral playlists don't have resume points. They wouldn't fit the full playlist
+                // when it's filtered, and smart playlists are played with whatever their rules
+                // pick right now, so where the user left off in them doesn't mean anything
+                let elements = playlist
+                    .elements
+                    .into_iter()
+                    .filter(|e| filter.matches(e))
+                    .collect();
+                let playlist = Playlist::ephemeral(current_user.id, elements);
+
+                play_command(
+                    current_user.id,
+                    playlist,
+                    element_index,
+                    song_index,
+                    seed,
+                    None,
+                    explicit_filter,
+                )?
+            }
+        }
+        Command::PlayElements {
+            elements,
+            element_index,
+            song_index,
+            seed,
+            explicit_filter,
         } => {
-            let playlist: Playlist =
-                sqlx::query_as("SELECT * FROM playlist WHERE id = $1 AND owner_id = $2")
-                    .bind(playlist_id)
-                    .bind(current_user.id)
-                    .fetch_optional(&state.db_pool)
-                    .await?
-                    .ok_or(GroovesError::NotFound)?;
-
-            PlayerCommand::Play {
+            let playlist = Playlist::ephemeral(current_user.id, elements);
+            let explicit_filter = explicit_filter.unwrap_or(current_user.explicit_filter);
+            play_command(
+                current_user.id,
                 playlist,
                 element_index,
                 song_index,
+                seed,
+                None,
+                explicit_filter,
+            )?
+        }
+        Command::PlayAlbums {
+            album_ids,
+            element_index,
+            song_index,
+            seed,
+            explicit_filter,
+        } => {
+            // Every album is fetched from spotify, so there can't be more than a playlist holds
+            if album_ids.len() > state.limits.max_elements {
+                let mut validator = Validator::default();
+                validator.error(
+                    "album_ids",
+                    format!("can't have more than {} albums", state.limits.max_elements),
+                );
+                validator.finish()?;
             }
+
+            let album_ids = album_ids
+                .into_iter()
+                .map(AlbumId::from_id)
+                .collect::<Result<Vec<_>, _>>()
+                .map_err(|_| GroovesError::InvalidRequest)?;
+
+            let token = current_user
+                .token
+                .clone()
+                .ok_or(GroovesError::Unauthorized)?;
+            let client = spotify::client_with_token(token.clone());
+
+            let elements = spotify::fetch_album_elements(&client, &album_ids).await?;
+            spotify::save_refreshed_token(&client, token, current_user.id, &state.db_pool).await?;
+
+            let playlist = Playlist::ephemeral(current_user.id, elements);
+            let explicit_filter = explicit_filter.unwrap_or(current_user.explicit_filter);
+            play_command(
+                current_user.id,
+                playlist,
+                element_index,
+                song_index,
+                seed,
+                None,
+                explicit_filter,
+            )?
         }
         Command::Pause => PlayerCommand::Pause,
         Command::Resume => PlayerCommand::Resume,
@@ -142,14 +313,178 @@ pub async fn command_handler(
         Command::PrevSong => PlayerCommand::PrevSong,
         Command::NextElement => PlayerCommand::NextElement,
         Command::PrevElement => PlayerCommand::PrevElement,
+        Command::SetRadio { enabled } => PlayerCommand::SetRadio { enabled },
+        Command::KeepGenerated => PlayerCommand::KeepGenerated,
+        Command::DiscardGenerated => PlayerCommand::DiscardGenerated,
         Command::AddToQueue => PlayerCommand::AddToQueue,
         Command::RemoveFromQueue => PlayerCommand::RemoveFromQueue,
         Command::Exit => PlayerCommand::Exit,
     };
 
+    let user_id = current_user.id;
+    let is_play = matches!(player_command, PlayerCommand::Play { .. });
+
     if manager.send_command(current_user, player_command).is_ok() {
+        if is_play {
+            spawn_play_recorder(&state, user_id);
+        }
+
         Ok("sent command")
     } else {
         Err(GroovesError::InternalError(anyhow!("command failed")))
     }
 }
+
+/// Validates a playlist before handing it to the player
+fn play_command(
+    user_id: i32,
+    playlist: Playlist,
+    element_index: Option<usize>,
+    song_index: Option<usize>,
+    seed: Option<u32>,
+    resume_point: Option<ResumePoint>,
+    explicit_filter: ExplicitFilter,
+) -> GroovesResult<PlayerCommand> {
+    validate_play(&playlist, element_index, song_index, explicit_filter).map_err(|e| {
+        warn!(error=?e, playlist_id = playlist.id, "invalid play command");
+        GroovesError::InvalidRequest
+    })?;
+
+    Ok(PlayerCommand::Play {
+        user_id,
+        playlist,
+        element_index,
+        song_index,
+        seed,
+        resume_point,
+        explicit_filter,
+    })
+}
+
+async fn fetch_resume_point(
+    state: &AppState,
+    user_id: i32,
+    playlist_id: i32,
+) -> GroovesResult<Option<ResumePoint>> {
+    let resume_point =
+        sqlx::query_as("SELECT * FROM resume_point WHERE user_id = $1 AND playlist_id = $2")
+            .bind(user_id)
+            .bind(playlist_id)
+            .fetch_optional(&state.db_pool)
+            .await?;
+
+    Ok(resume_point)
+}
+
+async fn get_resume_point(
+    State(state): State<AppState>,
+    Extension(current_user): Extension<User>,
+    Path(playlist_id): Path<i32>,
+) -> GroovesResult<impl IntoResponse> {
+    let resume_point = fetch_resume_point(&state, current_user.id, playlist_id)
+        .await?
+        .ok_or(GroovesError::NotFound)?;
+
+    Ok(Json(resume_point))
+}
+
+/// Starts a task that stores the user's resume points and album plays as their players report
+/// them. Only one task is started per user, and it outlives any single player
+fn spawn_play_recorder(state: &AppState, user_id: i32) {
+    if !state.play_recorders.lock().unwrap().insert(user_id) {
+        return;
+    }
+
+    let state = state.clone();
+    tokio::spawn(async move {
+        loop {
+            let connection = state.player_manager.await_player_connection(user_id).await;
+            let mut resume_receiver = connection.resume_receiver;
+            let mut album_receiver = connection.album_receiver;
+
+            loop {
+                tokio::select! {
+                    changed = resume_receiver.changed() => {
+                        if changed.is_err() {
+                            break;
+                        }
+                        let resume_point = resume_receiver.borrow_and_update().clone();
+                        if let Some(resume_point) = resume_point {
+                            save_resume_point(&state, user_id, resume_point).await;
+                        }
+                    }
+                    changed = album_receiver.changed() => {
+                        if changed.is_err() {
+                            break;
+                        }
+                        let album_id = album_receiver.borrow_and_update().clone();
+                        if let Some(album_id) = album_id {
+                            if let Err(e) =
+                                smart::record_album_play(&state.db_pool, user_id, &album_id).await
+                            {
+                                warn!(error=?e, user_id, "failed to record album play");
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    });
+}
+
+async fn save_resume_point(state: &AppState, user_id: i32, resume_point: ResumePoint) {
+    let res = sqlx::query(
+        r#"INSERT INTO resume_point (user_id, playlist_id, element_order, current_element, current_song, seed)
+            VALUES ($1, $2, $3, $4, $5, $6)
+            ON CONFLICT (user_id, playlist_id) DO UPDATE
+            SET element_order = $3, current_element = $4, current_song = $5, seed = $6, updated_at = now()"#,
+    )
+    .bind(user_id)
+    .bind(resume_point.playlist_id)
+    .bind(sqlx::types::Json(resume_point.element_order))
+    .bind(resume_point.current_element)
+    .bind(resume_point.current_song)
+    .bind(resume_point.seed)
+    .execute(&state.db_pool)
+    .await;
+
+    if let Err(e) = res {
+        warn!(error=?e, user_id, "failed to save resume point");
+    }
+}
+
+#[derive(Deserialize, Clone, Debug)]
+struct SavePlaylist {
+    name: String,
+}
+
+/// Saves what's currently playing as a new playlist, including any generated elements that were
+/// kept. Only plays that aren't of a saved playlist can be saved, so playlists aren't duplicated
+async fn save_playlist(
+    State(state): State<AppState>,
+    Extension(current_user): Extension<User>,
+    Json(payload): Json<SavePlaylist>,
+) -> GroovesResult<impl IntoResponse> {
+    let playing = state
+        .player_manager
+        .get_player_connection(current_user.id)
+        .and_then(|connection| connection.playlist_receiver.borrow().clone())
+        .ok_or(GroovesError::NotFound)?;
+    if !playing.is_ephemeral() {
+        return Err(GroovesError::InvalidRequest);
+    }
+    validate_playlist(&payload.name, &playing.elements, &state.limits)?;
+
+    // Smart playlists are played without looking up their songs' ISRCs
+    let mut elements = playing.elements;
+    let token = current_user.token.ok_or(GroovesError::Unauthorized)?;
+    let client = spotify::client_with_token(token.clone());
+    spotify::fill_isrcs(&client, &mut elements).await?;
+    spotify::save_refreshed_token(&client, token, current_user.id, &state.db_pool).await?;
+
+    let mut tx = state.db_pool.begin().await?;
+    let playlist = db::create_playlist(&mut tx, payload.name, current_user.id, elements).await?;
+    tx.commit().await?;
+
+    Ok(Json(playlist))
+}
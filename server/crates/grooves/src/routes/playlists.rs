@@ -1,43 +1,225 @@
-use axum::extract::{Path, State};
-use axum::http::StatusCode;
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+
+use axum::extract::{Path, Query, State};
+use axum::http::header::{ETAG, IF_MATCH, IF_NONE_MATCH};
+use axum::http::{HeaderMap, StatusCode};
 use axum::response::IntoResponse;
-use axum::routing::get;
+use axum::routing::{get, post, put};
 use axum::{Extension, Json, Router};
 use axum_macros::debug_handler;
-use grooves_model::{Playlist, PlaylistElement, User};
-use serde::Deserialize;
+use grooves_model::{
+    ElementFilter, Playlist, PlaylistElement, PlaylistFolder, PlaylistPlacement, PlaylistRole,
+    PlaylistSummary, PlaylistTree, User,
+};
+use serde::{Deserialize, Serialize};
 use tracing::info;
 
+use crate::db::folders;
+use crate::db::playlists as db;
 use crate::error::{GroovesError, GroovesResult};
-use crate::{middleware, AppState};
+use crate::util::spotify;
+use crate::validation::{validate_playlist, JsonBody};
+use crate::{metadata, middleware, AppState};
+
+mod combine;
+mod elements;
+mod files;
+mod members;
+mod revisions;
+mod shares;
+mod smart;
 
 pub fn router(state: AppState) -> Router<AppState> {
     info!("Creating playlist routes");
 
     Router::new()
         .route("/", get(get_playlists).post(create_playlist))
+        .route("/tree", get(get_playlist_tree))
         .route(
             "/:playlistId",
             get(get_playlist)
                 .put(update_playlist)
                 .delete(delete_playlist),
         )
+        .route("/:playlistId/refresh", post(refresh_playlist))
+        .route("/:playlistId/placement", put(update_placement))
+        .merge(combine::router())
+        .merge(files::router())
+        .merge(smart::router())
+        .nest("/:playlistId", elements::router())
+        .nest("/:playlistId", members::router())
+        .nest("/:playlistId/revisions", revisions::router())
+        .nest("/:playlistId/shares", shares::router())
         .route_layer(axum::middleware::from_fn_with_state(
             state,
             middleware::auth::auth,
         ))
 }
 
+/// The most playlists a page of the list can have
+const MAX_PAGE_SIZE: i64 = 200;
+
+const DEFAULT_PAGE_SIZE: i64 = 50;
+
+#[derive(Deserialize, Clone, Copy, Debug, Default)]
+#[serde(rename_all = "snake_case")]
+enum ListSort {
+    /// Pinned playlists first, then by the positions the user gave them
+    #[default]
+    Manual,
+    Name,
+    /// When the playlist was created
+    Created,
+    ElementCount,
+    Duration,
+}
+
+#[derive(Deserialize, Clone, Copy, Debug, Default)]
+#[serde(rename_all = "snake_case")]
+enum SortDirection {
+    #[default]
+    Asc,
+    Desc,
+}
+
+#[derive(Deserialize, Clone, Debug)]
+struct ListParams {
+    #[serde(default)]
+    include_archived: bool,
+    /// Playlists whose names or elements' names or artists contain this, ignoring case
+    search: Option<String>,
+    #[serde(default)]
+    sort: ListSort,
+    #[serde(default)]
+    direction: SortDirection,
+    #[serde(default)]
+    offset: i64,
+    limit: Option<i64>,
+}
+
+impl ListParams {
+    fn order_by(&self) -> String {
+        let direction = match self.direction {
+            SortDirection::Asc => "ASC",
+            SortDirection::Desc => "DESC",
+        };
+
+        match self.sort {
+            ListSort::Manual => format!("m.pinned DESC, m.position {direction}, p.id {direction}"),
+            ListSort::Name => format!("LOWER(p.name) {direction}, p.id"),
+            ListSort::Created => format!("p.id {direction}"),
+            ListSort::ElementCount => format!("element_count {direction}, p.id"),
+            ListSort::Duration => format!("duration_ms {direction}, p.id"),
+        }
+    }
+
+    /// The search as a LIKE pattern
+    fn search_pattern(&self) -> Option<String> {
+        let search = self.search.as_deref().filter(|s| !s.is_empty())?;
+        let escaped = search
+            .replace('\\', "\\\\")
+            .replace('%', "\\%")
+            .replace('_', "\\_");
+        Some(format!("%{escaped}%"))
+    }
+}
+
+#[derive(Serialize, Clone, Debug, Hash)]
+struct PlaylistPage {
+    playlists: Vec<PlaylistSummary>,
+    /// How many playlists there are in every page together
+    total: i64,
+}
+
+/// A page of summaries of the user's playlists. Elements are only in single playlists
 async fn get_playlists(
     State(state): State<AppState>,
     Extension(current_user): Extension<User>,
+    Query(params): Query<ListParams>,
+    headers: HeaderMap,
 ) -> GroovesResult<impl IntoResponse> {
-    let playlists: Vec<Playlist> = sqlx::query_as("SELECT * FROM playlist WHERE owner_id = $1")
-        .bind(current_user.id)
-        .fetch_all(&state.db_pool)
-        .await?;
+    let limit = params.limit.unwrap_or(DEFAULT_PAGE_SIZE);
+    if !(1..=MAX_PAGE_SIZE).contains(&limit) || params.offset < 0 {
+        return Err(GroovesError::InvalidRequest);
+    }
+
+    let mut conn = state.db_pool.acquire().await?;
+    let search = params.search_pattern();
+
+    let total = db::count_summaries(
+        &mut conn,
+        current_user.id,
+        params.include_archived,
+        search.as_deref(),
+    )
+    .await?;
+    let playlists = db::select_summaries(
+        &mut conn,
+        current_user.id,
+        params.include_archived,
+        search.as_deref(),
+        &params.order_by(),
+        limit,
+        params.offset,
+    )
+    .await?;
+
+    let page = PlaylistPage { playlists, total };
+    let etag = hash_etag(&page);
+
+    if is_not_modified(&headers, &etag) {
+        return Ok(StatusCode::NOT_MODIFIED.into_response());
+    }
+
+    Ok(([(ETAG, etag)], Json(page)).into_response())
+}
 
-    Ok(Json(playlists))
+#[derive(Deserialize, Clone, Copy, Debug, Default)]
+struct TreeParams {
+    #[serde(default)]
+    include_archived: bool,
+}
+
+/// Summaries of all the user's playlists in their folders
+async fn get_playlist_tree(
+    State(state): State<AppState>,
+    Extension(current_user): Extension<User>,
+    Query(params): Query<TreeParams>,
+    headers: HeaderMap,
+) -> GroovesResult<impl IntoResponse> {
+    let mut conn = state.db_pool.acquire().await?;
+
+    let playlists = db::select_summaries(
+        &mut conn,
+        current_user.id,
+        params.include_archived,
+        None,
+        "p.id",
+        i64::MAX,
+        0,
+    )
+    .await?;
+
+    let folders: Vec<PlaylistFolder> =
+        sqlx::query_as("SELECT * FROM playlist_folder WHERE user_id = $1 ORDER BY id")
+            .bind(current_user.id)
+            .fetch_all(&mut *conn)
+            .await?;
+
+    let etag = hash_etag(&(&folders, &playlists));
+    if is_not_modified(&headers, &etag) {
+        return Ok(StatusCode::NOT_MODIFIED.into_response());
+    }
+
+    Ok(([(ETAG, etag)], Json(PlaylistTree::new(folders, playlists))).into_response())
+}
+
+/// A weak etag for a response that isn't a single playlist, so it has no version of its own
+fn hash_etag(value: &impl Hash) -> String {
+    let mut hasher = DefaultHasher::new();
+    value.hash(&mut hasher);
+    format!("W/\"{:x}\"", hasher.finish())
 }
 
 #[derive(Deserialize, Clone, Debug)]
@@ -50,73 +232,214 @@ struct CreatePlaylist {
 async fn create_playlist(
     State(state): State<AppState>,
     Extension(current_user): Extension<User>,
-    Json(payload): Json<CreatePlaylist>,
+    JsonBody(payload): JsonBody<CreatePlaylist>,
 ) -> GroovesResult<impl IntoResponse> {
-    let playlist: Playlist = sqlx::query_as(
-        "INSERT INTO playlist (name, owner_id, elements) VALUES ($1, $2, $3) RETURNING *",
-    )
-    .bind(payload.name)
-    .bind(current_user.id)
-    .bind(sqlx::types::Json::from(payload.elements))
-    .fetch_one(&state.db_pool)
-    .await?;
+    validate_playlist(&payload.name, &payload.elements, &state.limits)?;
 
-    Ok(Json(playlist))
+    let mut tx = state.db_pool.begin().await?;
+    let playlist =
+        db::create_playlist(&mut tx, payload.name, current_user.id, payload.elements).await?;
+    tx.commit().await?;
+
+    Ok(([(ETAG, etag(playlist.version))], Json(playlist)))
 }
 
 async fn get_playlist(
     State(state): State<AppState>,
     Extension(current_user): Extension<User>,
     Path(playlist_id): Path<i32>,
+    Query(filter): Query<ElementFilter>,
+    headers: HeaderMap,
 ) -> GroovesResult<impl IntoResponse> {
-    let playlist: Playlist =
-        sqlx::query_as("SELECT * FROM playlist WHERE id = $1 AND owner_id = $2")
-            .bind(playlist_id)
-            .bind(current_user.id)
-            .fetch_optional(&state.db_pool)
-            .await?
-            .ok_or(GroovesError::NotFound)?;
+    let mut conn = state.db_pool.acquire().await?;
 
-    Ok(Json(playlist))
+    db::require_role(
+        &mut conn,
+        playlist_id,
+        current_user.id,
+        PlaylistRole::Viewer,
+    )
+    .await?;
+
+    let mut playlist: Playlist = sqlx::query_as(&format!(
+        "SELECT p.id, p.name, {} AS owner_id, p.version FROM playlist p WHERE p.id = $1",
+        db::CURRENT_OWNER
+    ))
+    .bind(playlist_id)
+    .fetch_optional(&mut *conn)
+    .await?
+    .ok_or(GroovesError::NotFound)?;
+
+    let etag = etag(playlist.version);
+    if filter.is_empty() && is_not_modified(&headers, &etag) {
+        return Ok(StatusCode::NOT_MODIFIED.into_response());
+    }
+
+    db::load_elements(&mut conn, std::slice::from_mut(&mut playlist)).await?;
+
+    if !filter.is_empty() {
+        playlist.elements.retain(|e| filter.matches(e));
+        return Ok(Json(playlist).into_response());
+    }
+
+    Ok(([(ETAG, etag)], Json(playlist)).into_response())
 }
 
 async fn update_playlist(
     State(state): State<AppState>,
     Extension(current_user): Extension<User>,
     Path(playlist_id): Path<i32>,
-    Json(payload): Json<CreatePlaylist>,
+    headers: HeaderMap,
+    JsonBody(payload): JsonBody<CreatePlaylist>,
 ) -> GroovesResult<impl IntoResponse> {
-    let playlist: Playlist = sqlx::query_as(
+    validate_playlist(&payload.name, &payload.elements, &state.limits)?;
+
+    let mut tx = state.db_pool.begin().await?;
+
+    let playlist =
+        db::lock_playlist(&mut tx, playlist_id, current_user.id, PlaylistRole::Editor).await?;
+    check_if_match(&headers, playlist.version)?;
+    if !payload.elements.is_empty() {
+        db::require_not_smart(&mut tx, playlist_id).await?;
+    }
+
+    let mut playlist: Playlist = sqlx::query_as(
         r#"UPDATE playlist
-            SET name = $1, elements = $2
-            WHERE id = $3 AND owner_id = $4
+            SET name = $1, version = version + 1
+            WHERE id = $2
             RETURNING *"#,
     )
     .bind(payload.name)
-    .bind(sqlx::types::Json::from(payload.elements))
     .bind(playlist_id)
-    .bind(current_user.id)
-    .fetch_optional(&state.db_pool)
-    .await?
-    .ok_or(GroovesError::NotFound)?;
+    .fetch_one(&mut *tx)
+    .await?;
 
-    Ok(Json(playlist))
+    db::replace_elements(&mut tx, playlist_id, &payload.elements).await?;
+    playlist.elements = payload.elements;
+
+    db::record_revision(&mut tx, &playlist, current_user.id).await?;
+    tx.commit().await?;
+
+    Ok(([(ETAG, etag(playlist.version))], Json(playlist)))
 }
 
 async fn delete_playlist(
     State(state): State<AppState>,
     Extension(current_user): Extension<User>,
     Path(playlist_id): Path<i32>,
+    headers: HeaderMap,
 ) -> GroovesResult<impl IntoResponse> {
-    let res = sqlx::query("DELETE FROM playlist WHERE id = $1 AND owner_id = $2")
+    let mut tx = state.db_pool.begin().await?;
+
+    let playlist =
+        db::lock_playlist(&mut tx, playlist_id, current_user.id, PlaylistRole::Owner).await?;
+    check_if_match(&headers, playlist.version)?;
+
+    sqlx::query("DELETE FROM playlist WHERE id = $1")
         .bind(playlist_id)
-        .bind(current_user.id)
-        .execute(&state.db_pool)
+        .execute(&mut *tx)
         .await?;
+    tx.commit().await?;
+
+    Ok(StatusCode::OK)
+}
+
+/// Moves the playlist to another folder or position in the current user's list, or pins or
+/// archives it. This doesn't change the playlist, so any member can do it
+async fn update_placement(
+    State(state): State<AppState>,
+    Extension(current_user): Extension<User>,
+    Path(playlist_id): Path<i32>,
+    Json(placement): Json<PlaylistPlacement>,
+) -> GroovesResult<impl IntoResponse> {
+    let mut conn = state.db_pool.acquire().await?;
+    db::require_role(
+        &mut conn,
+        playlist_id,
+        current_user.id,
+        PlaylistRole::Viewer,
+    )
+    .await?;
 
-    if res.rows_affected() == 0 {
-        Err(GroovesError::NotFound)
+    if let Some(folder_id) = placement.folder_id {
+        folders::require_folder(&mut conn, folder_id, current_user.id).await?;
+    }
+
+    sqlx::query(
+        r#"UPDATE playlist_member SET folder_id = $3, position = $4, pinned = $5, archived = $6
+            WHERE playlist_id = $1 AND user_id = $2"#,
+    )
+    .bind(playlist_id)
+    .bind(current_user.id)
+    .bind(placement.folder_id)
+    .bind(placement.position)
+    .bind(placement.pinned)
+    .bind(placement.archived)
+    .execute(&mut *conn)
+    .await?;
+
+    Ok(Json(placement))
+}
+
+/// Updates the playlist's songs and albums to how they are on spotify now
+async fn refresh_playlist(
+    State(state): State<AppState>,
+    Extension(current_user): Extension<User>,
+    Path(playlist_id): Path<i32>,
+) -> GroovesResult<impl IntoResponse> {
+    let mut conn = state.db_pool.acquire().await?;
+    db::require_role(
+        &mut conn,
+        playlist_id,
+        current_user.id,
+        PlaylistRole::Editor,
+    )
+    .await?;
+    drop(conn);
+
+    let token = current_user.token.ok_or(GroovesError::Unauthorized)?;
+    let client = spotify::client_with_token(token.clone());
+
+    let report =
+        metadata::refresh_playlist(&state.db_pool, &client, playlist_id, current_user.id).await?;
+
+    spotify::save_refreshed_token(&client, token, current_user.id, &state.db_pool).await?;
+
+    Ok(([(ETAG, etag(report.version))], Json(report)))
+}
+
+fn etag(version: i32) -> String {
+    format!("\"{version}\"")
+}
+
+/// Fails if the request has an If-Match header that doesn't match the playlist's version
+fn check_if_match(headers: &HeaderMap, version: i32) -> GroovesResult<()> {
+    let Some(if_match) = headers.get(IF_MATCH) else {
+        return Ok(());
+    };
+
+    let if_match = if_match
+        .to_str()
+        .map_err(|_| GroovesError::InvalidRequest)?;
+
+    if if_match.trim() == "*" || etag_list_contains(if_match, &etag(version)) {
+        Ok(())
     } else {
-        Ok(StatusCode::OK)
+        Err(GroovesError::PreconditionFailed)
     }
 }
+
+/// Whether the request has an If-None-Match header that matches the etag
+fn is_not_modified(headers: &HeaderMap, etag: &str) -> bool {
+    headers
+        .get(IF_NONE_MATCH)
+        .and_then(|h| h.to_str().ok())
+        .is_some_and(|if_none_match| etag_list_contains(if_none_match, etag))
+}
+
+/// Compares etags ignoring whether they're weak
+fn etag_list_contains(list: &str, etag: &str) -> bool {
+    let etag = etag.trim_start_matches("W/");
+    list.split(',')
+        .any(|tag| tag.trim().trim_start_matches("W/") == etag)
+}
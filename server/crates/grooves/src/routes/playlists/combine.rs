@@ -0,0 +1,158 @@
+use axum::extract::{Path, State};
+use axum::http::header::ETAG;
+use axum::response::IntoResponse;
+use axum::routing::post;
+use axum::{Extension, Json, Router};
+use grooves_model::{
+    intersect_elements, merge_elements, subtract_elements, Playlist, PlaylistElement, PlaylistRole,
+    User,
+};
+use serde::Deserialize;
+
+use super::etag;
+use crate::db::playlists as db;
+use crate::error::{GroovesError, GroovesResult};
+use crate::validation::validate_playlist;
+use crate::{smart, AppState};
+
+pub fn router() -> Router<AppState> {
+    Router::new()
+        .route("/:playlistId/duplicate", post(duplicate_playlist))
+        .route("/merge", post(merge))
+        .route("/intersection", post(intersection))
+        .route("/difference", post(difference))
+}
+
+#[derive(Deserialize, Clone, Debug)]
+struct DuplicatePlaylist {
+    /// Defaults to the original's name
+    name: Option<String>,
+}
+
+/// Copies a playlist the user can view into a new one they own. Copies of smart playlists
+/// keep their rules
+async fn duplicate_playlist(
+    State(state): State<AppState>,
+    Extension(current_user): Extension<User>,
+    Path(playlist_id): Path<i32>,
+    Json(payload): Json<DuplicatePlaylist>,
+) -> GroovesResult<impl IntoResponse> {
+    let mut conn = state.db_pool.acquire().await?;
+    db::require_role(
+        &mut conn,
+        playlist_id,
+        current_user.id,
+        PlaylistRole::Viewer,
+    )
+    .await?;
+
+    let mut original: Playlist = sqlx::query_as("SELECT * FROM playlist WHERE id = $1")
+        .bind(playlist_id)
+        .fetch_one(&mut *conn)
+        .await?;
+    db::load_elements(&mut conn, std::slice::from_mut(&mut original)).await?;
+    let rules = db::smart_rules(&mut conn, playlist_id).await?;
+    drop(conn);
+
+    let name = payload.name.unwrap_or(original.name);
+    validate_playlist(&name, &original.elements, &state.limits)?;
+
+    let mut tx = state.db_pool.begin().await?;
+    let playlist = db::create_playlist(&mut tx, name, current_user.id, original.elements).await?;
+    if let Some(rules) = rules {
+        db::insert_smart_rules(&mut tx, playlist.id, &rules).await?;
+    }
+    tx.commit().await?;
+
+    Ok(([(ETAG, etag(playlist.version))], Json(playlist)))
+}
+
+#[derive(Deserialize, Clone, Debug)]
+struct Combine {
+    playlist_ids: Vec<i32>,
+    /// Saves the result as a new playlist with this name. Without it the result is only
+    /// returned, as an unsaved playlist
+    name: Option<String>,
+}
+
+/// The elements of every playlist, with each album only included once
+async fn merge(
+    State(state): State<AppState>,
+    Extension(current_user): Extension<User>,
+    Json(payload): Json<Combine>,
+) -> GroovesResult<impl IntoResponse> {
+    combine(&state, &current_user, payload, merge_elements).await
+}
+
+/// The elements of the first playlist whose albums are in all the others
+async fn intersection(
+    State(state): State<AppState>,
+    Extension(current_user): Extension<User>,
+    Json(payload): Json<Combine>,
+) -> GroovesResult<impl IntoResponse> {
+    combine(&state, &current_user, payload, |mut lists| {
+        let first = lists.remove(0);
+        intersect_elements(first, &lists)
+    })
+    .await
+}
+
+/// The elements of the first playlist whose albums aren't in any of the others
+async fn difference(
+    State(state): State<AppState>,
+    Extension(current_user): Extension<User>,
+    Json(payload): Json<Combine>,
+) -> GroovesResult<impl IntoResponse> {
+    combine(&state, &current_user, payload, |mut lists| {
+        let first = lists.remove(0);
+        subtract_elements(first, &lists)
+    })
+    .await
+}
+
+/// Combines the elements of playlists the user can view. Smart playlists are used with the
+/// elements their rules pick right now
+async fn combine(
+    state: &AppState,
+    current_user: &User,
+    payload: Combine,
+    operation: impl FnOnce(Vec<Vec<PlaylistElement>>) -> Vec<PlaylistElement>,
+) -> GroovesResult<impl IntoResponse> {
+    if payload.playlist_ids.is_empty() {
+        return Err(GroovesError::InvalidRequest);
+    }
+
+    let mut lists = Vec::with_capacity(payload.playlist_ids.len());
+    for &playlist_id in &payload.playlist_ids {
+        let mut conn = state.db_pool.acquire().await?;
+        db::require_role(
+            &mut conn,
+            playlist_id,
+            current_user.id,
+            PlaylistRole::Viewer,
+        )
+        .await?;
+
+        let mut playlist: Playlist = sqlx::query_as("SELECT * FROM playlist WHERE id = $1")
+            .bind(playlist_id)
+            .fetch_one(&mut *conn)
+            .await?;
+        drop(conn);
+
+        smart::load_elements(&state.db_pool, current_user, &mut playlist).await?;
+        lists.push(playlist.elements);
+    }
+
+    let elements = operation(lists);
+
+    let Some(name) = payload.name else {
+        return Ok(Json(Playlist::ephemeral(current_user.id, elements)).into_response());
+    };
+    validate_playlist(&name, &elements, &state.limits)?;
+
+    let mut tx = state.db_pool.begin().await?;
+    let playlist = db::create_playlist(&mut tx, name, current_user.id, elements).await?;
+    tx.commit().await?;
+
+    Ok(([(ETAG, etag(playlist.version))], Json(playlist)).into_response())
+}
@@ -0,0 +1,420 @@
+use axum::extract::{Path, State};
+use axum::http::header::ETAG;
+use axum::http::HeaderMap;
+use axum::response::IntoResponse;
+use axum::routing::{delete, post, put};
+use axum::{Extension, Router};
+use grooves_model::{ElementAnnotations, PlaylistElement, PlaylistRole, User};
+use serde::Deserialize;
+use sqlx::PgConnection;
+
+use super::{check_if_match, etag};
+use crate::db::playlists as db;
+use crate::error::{GroovesError, GroovesResult};
+use crate::validation::{JsonBody, Limits, Validator};
+use crate::AppState;
+
+pub fn router() -> Router<AppState> {
+    Router::new()
+        .route("/elements", post(add_elements))
+        .route("/elements/:position", delete(remove_element))
+        .route("/elements/:position/move", post(move_element))
+        .route("/elements/:position/exclusions", post(set_excluded))
+        .route("/elements/:position/range", post(select_song_range))
+        .route("/elements/:position/annotations", put(set_annotations))
+        .route("/operations", post(apply_operations))
+}
+
+/// A single change to a playlist's elements. Positions are indices into the elements
+/// as they are when the operation is applied
+#[derive(Deserialize, Clone, Debug)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum ElementOperation {
+    Append {
+        elements: Vec<PlaylistElement>,
+    },
+    Insert {
+        position: usize,
+        elements: Vec<PlaylistElement>,
+    },
+    Remove {
+        position: usize,
+    },
+    Move {
+        from: usize,
+        to: usize,
+    },
+    /// Excludes songs from playback or includes them again. Songs are indices into the element
+    SetExcluded {
+        position: usize,
+        songs: Vec<usize>,
+        excluded: bool,
+    },
+    /// Only plays the songs from `start` up to but not including `end`, e.g. one side of a record
+    SelectRange {
+        position: usize,
+        start: usize,
+        end: usize,
+    },
+    /// Replaces the element's tags, note, rating and shuffle weight
+    Annotate {
+        position: usize,
+        #[serde(flatten)]
+        annotations: ElementAnnotations,
+    },
+}
+
+#[derive(Deserialize, Clone, Debug)]
+struct AddElements {
+    /// Where to insert the elements, or at the end if None
+    position: Option<usize>,
+    elements: Vec<PlaylistElement>,
+}
+
+async fn add_elements(
+    State(state): State<AppState>,
+    Extension(current_user): Extension<User>,
+    Path(playlist_id): Path<i32>,
+    headers: HeaderMap,
+    JsonBody(payload): JsonBody<AddElements>,
+) -> GroovesResult<impl IntoResponse> {
+    let operation = match payload.position {
+        Some(position) => ElementOperation::Insert {
+            position,
+            elements: payload.elements,
+        },
+        None => ElementOperation::Append {
+            elements: payload.elements,
+        },
+    };
+
+    apply(
+        &state,
+        &current_user,
+        playlist_id,
+        &headers,
+        vec![operation],
+    )
+    .await
+}
+
+async fn remove_element(
+    State(state): State<AppState>,
+    Extension(current_user): Extension<User>,
+    Path((playlist_id, position)): Path<(i32, usize)>,
+    headers: HeaderMap,
+) -> GroovesResult<impl IntoResponse> {
+    let operation = ElementOperation::Remove { position };
+
+    apply(
+        &state,
+        &current_user,
+        playlist_id,
+        &headers,
+        vec![operation],
+    )
+    .await
+}
+
+#[derive(Deserialize, Clone, Debug)]
+struct MoveElement {
+    to: usize,
+}
+
+async fn move_element(
+    State(state): State<AppState>,
+    Extension(current_user): Extension<User>,
+    Path((playlist_id, position)): Path<(i32, usize)>,
+    headers: HeaderMap,
+    JsonBody(payload): JsonBody<MoveElement>,
+) -> GroovesResult<impl IntoResponse> {
+    let operation = ElementOperation::Move {
+        from: position,
+        to: payload.to,
+    };
+
+    apply(
+        &state,
+        &current_user,
+        playlist_id,
+        &headers,
+        vec![operation],
+    )
+    .await
+}
+
+#[derive(Deserialize, Clone, Debug)]
+struct SetExcluded {
+    songs: Vec<usize>,
+    excluded: bool,
+}
+
+async fn set_excluded(
+    State(state): State<AppState>,
+    Extension(current_user): Extension<User>,
+    Path((playlist_id, position)): Path<(i32, usize)>,
+    headers: HeaderMap,
+    JsonBody(payload): JsonBody<SetExcluded>,
+) -> GroovesResult<impl IntoResponse> {
+    let operation = ElementOperation::SetExcluded {
+        position,
+        songs: payload.songs,
+        excluded: payload.excluded,
+    };
+
+    apply(
+        &state,
+        &current_user,
+        playlist_id,
+        &headers,
+        vec![operation],
+    )
+    .await
+}
+
+#[derive(Deserialize, Clone, Debug)]
+struct SelectRange {
+    start: usize,
+    end: usize,
+}
+
+async fn select_song_range(
+    State(state): State<AppState>,
+    Extension(current_user): Extension<User>,
+    Path((playlist_id, position)): Path<(i32, usize)>,
+    headers: HeaderMap,
+    JsonBody(payload): JsonBody<SelectRange>,
+) -> GroovesResult<impl IntoResponse> {
+    let operation = ElementOperation::SelectRange {
+        position,
+        start: payload.start,
+        end: payload.end,
+    };
+
+    apply(
+        &state,
+        &current_user,
+        playlist_id,
+        &headers,
+        vec![operation],
+    )
+    .await
+}
+
+async fn set_annotations(
+    State(state): State<AppState>,
+    Extension(current_user): Extension<User>,
+    Path((playlist_id, position)): Path<(i32, usize)>,
+    headers: HeaderMap,
+    JsonBody(annotations): JsonBody<ElementAnnotations>,
+) -> GroovesResult<impl IntoResponse> {
+    let operation = ElementOperation::Annotate {
+        position,
+        annotations,
+    };
+
+    apply(
+        &state,
+        &current_user,
+        playlist_id,
+        &headers,
+        vec![operation],
+    )
+    .await
+}
+
+/// Applies the operations in order. If any of them fail then none of them are applied
+async fn apply_operations(
+    State(state): State<AppState>,
+    Extension(current_user): Extension<User>,
+    Path(playlist_id): Path<i32>,
+    headers: HeaderMap,
+    JsonBody(operations): JsonBody<Vec<ElementOperation>>,
+) -> GroovesResult<impl IntoResponse> {
+    apply_list(
+        &state,
+        &current_user,
+        playlist_id,
+        &headers,
+        operations,
+        true,
+    )
+    .await
+}
+
+async fn apply(
+    state: &AppState,
+    current_user: &User,
+    playlist_id: i32,
+    headers: &HeaderMap,
+    operations: Vec<ElementOperation>,
+) -> GroovesResult<impl IntoResponse> {
+    apply_list(state, current_user, playlist_id, headers, operations, false).await
+}
+
+/// `indexed` is whether the operations were sent as a list, so the fields of validation errors
+/// start with the operation's index
+async fn apply_list(
+    state: &AppState,
+    current_user: &User,
+    playlist_id: i32,
+    headers: &HeaderMap,
+    operations: Vec<ElementOperation>,
+    indexed: bool,
+) -> GroovesResult<impl IntoResponse> {
+    let mut tx = state.db_pool.begin().await?;
+
+    // Locking the playlist makes concurrent edits wait for each other instead of interleaving
+    let mut playlist =
+        db::lock_playlist(&mut tx, playlist_id, current_user.id, PlaylistRole::Editor).await?;
+    check_if_match(headers, playlist.version)?;
+    db::require_not_smart(&mut tx, playlist_id).await?;
+
+    if operations.is_empty() {
+        return Ok([(ETAG, etag(playlist.version))]);
+    }
+
+    for (i, operation) in operations.into_iter().enumerate() {
+        let prefix = if indexed {
+            format!("[{i}]")
+        } else {
+            String::new()
+        };
+        apply_operation(&mut tx, playlist_id, operation, &prefix, &state.limits).await?;
+    }
+
+    playlist.version = db::bump_version(&mut tx, playlist_id).await?;
+    db::load_elements(&mut tx, std::slice::from_mut(&mut playlist)).await?;
+    db::record_revision(&mut tx, &playlist, current_user.id).await?;
+    tx.commit().await?;
+
+    Ok([(ETAG, etag(playlist.version))])
+}
+
+/// `prefix` is where the operation is in the request body, or empty if it's the whole body
+async fn apply_operation(
+    conn: &mut PgConnection,
+    playlist_id: i32,
+    operation: ElementOperation,
+    prefix: &str,
+    limits: &Limits,
+) -> GroovesResult<()> {
+    let field = if prefix.is_empty() {
+        "elements".to_owned()
+    } else {
+        format!("{prefix}.elements")
+    };
+
+    let len = db::count_elements(conn, playlist_id).await?;
+    let position = |index: usize, max: i32| {
+        i32::try_from(index)
+            .ok()
+            .filter(|&p| p <= max)
+            .ok_or(GroovesError::InvalidRequest)
+    };
+
+    match operation {
+        ElementOperation::Append { elements } => {
+            validate_added(conn, playlist_id, len, &elements, &field, limits).await?;
+            db::insert_elements(conn, playlist_id, len, &elements).await
+        }
+        ElementOperation::Insert {
+            position: index,
+            elements,
+        } => {
+            let position = position(index, len)?;
+            validate_added(conn, playlist_id, len, &elements, &field, limits).await?;
+            db::insert_elements_at(conn, playlist_id, position, &elements).await
+        }
+        ElementOperation::Remove { position: index } => {
+            let position = position(index, len - 1)?;
+            db::remove_element(conn, playlist_id, position).await?;
+            Ok(())
+        }
+        ElementOperation::Move { from, to } => {
+            let from = position(from, len - 1)?;
+            let to = position(to, len - 1)?;
+            db::move_element(conn, playlist_id, from, to).await
+        }
+        ElementOperation::SetExcluded {
+            position: index,
+            songs,
+            excluded,
+        } => {
+            let position = position(index, len - 1)?;
+            let song_count = db::count_songs(conn, playlist_id, position).await?;
+            let songs = songs
+                .into_iter()
+                .map(|song| {
+                    i32::try_from(song)
+                        .ok()
+                        .filter(|&s| s < song_count)
+                        .ok_or(GroovesError::InvalidRequest)
+                })
+                .collect::<GroovesResult<Vec<i32>>>()?;
+
+            db::set_excluded(conn, playlist_id, position, &songs, excluded).await?;
+            require_playable_songs(conn, playlist_id, position).await
+        }
+        ElementOperation::SelectRange {
+            position: index,
+            start,
+            end,
+        } => {
+            let position = position(index, len - 1)?;
+            let song_count = db::count_songs(conn, playlist_id, position).await?;
+            let (Ok(start), Ok(end)) = (i32::try_from(start), i32::try_from(end)) else {
+                return Err(GroovesError::InvalidRequest);
+            };
+            if start >= end || end > song_count {
+                return Err(GroovesError::InvalidRequest);
+            }
+
+            db::select_song_range(conn, playlist_id, position, start, end).await?;
+            require_playable_songs(conn, playlist_id, position).await
+        }
+        ElementOperation::Annotate {
+            position: index,
+            annotations,
+        } => {
+            let position = position(index, len - 1)?;
+
+            let mut validator = Validator::default();
+            validator.annotations(prefix, &annotations, limits);
+            validator.finish()?;
+
+            db::set_annotations(conn, playlist_id, position, &annotations).await?;
+            Ok(())
+        }
+    }
+}
+
+/// Elements left with nothing to play are skipped by the player, which is fine when spotify
+/// makes their songs unavailable but a mistake when it's done by hand
+async fn require_playable_songs(
+    conn: &mut PgConnection,
+    playlist_id: i32,
+    position: i32,
+) -> GroovesResult<()> {
+    if db::has_playable_songs(conn, playlist_id, position).await? {
+        Ok(())
+    } else {
+        Err(GroovesError::InvalidRequest)
+    }
+}
+
+/// Checks elements that are about to be added to a playlist that has `len` elements
+async fn validate_added(
+    conn: &mut PgConnection,
+    playlist_id: i32,
+    len: i32,
+    elements: &[PlaylistElement],
+    field: &str,
+    limits: &Limits,
+) -> GroovesResult<()> {
+    let existing_album_ids = db::album_ids(conn, playlist_id).await?;
+
+    let mut validator = Validator::default();
+    validator.elements(field, elements, len as usize, &existing_album_ids, limits);
+    validator.finish()
+}
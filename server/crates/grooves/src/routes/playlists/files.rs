@@ -0,0 +1,208 @@
+use axum::extract::{Path, Query, State};
+use axum::http::header::{CONTENT_DISPOSITION, CONTENT_TYPE};
+use axum::response::IntoResponse;
+use axum::routing::{get, post};
+use axum::{Extension, Json, Router};
+use grooves_model::{Playlist, PlaylistElement, PlaylistRole, User};
+use itertools::Itertools;
+use rspotify::AuthCodeSpotify;
+use serde::Deserialize;
+use serde_json::json;
+use tracing::{debug, warn};
+
+use crate::db::playlists as db;
+use crate::error::{GroovesError, GroovesResult};
+use crate::util::playlist_files::{self, GroovesFile, InvalidEntry};
+use crate::util::spotify;
+use crate::validation::validate_playlist;
+use crate::{metadata, smart, AppState};
+
+pub fn router() -> Router<AppState> {
+    Router::new()
+        .route("/import", post(import_playlist))
+        .route("/:playlistId/export", get(export_playlist))
+}
+
+#[derive(Deserialize, Clone, Copy, Debug, Default)]
+#[serde(rename_all = "snake_case")]
+enum ExportFormat {
+    #[default]
+    Grooves,
+    Xspf,
+    M3u,
+}
+
+#[derive(Deserialize, Clone, Debug)]
+struct ExportParams {
+    #[serde(default)]
+    format: ExportFormat,
+}
+
+async fn export_playlist(
+    State(state): State<AppState>,
+    Extension(current_user): Extension<User>,
+    Path(playlist_id): Path<i32>,
+    Query(params): Query<ExportParams>,
+) -> GroovesResult<impl IntoResponse> {
+    let mut conn = state.db_pool.acquire().await?;
+    db::require_role(
+        &mut conn,
+        playlist_id,
+        current_user.id,
+        PlaylistRole::Viewer,
+    )
+    .await?;
+
+    let mut playlist: Playlist = sqlx::query_as("SELECT * FROM playlist WHERE id = $1")
+        .bind(playlist_id)
+        .fetch_one(&mut *conn)
+        .await?;
+    drop(conn);
+    smart::load_elements(&state.db_pool, &current_user, &mut playlist).await?;
+
+    let (content_type, extension, body) = match params.format {
+        ExportFormat::Grooves => (
+            "application/json",
+            "json",
+            serde_json::to_string_pretty(&GroovesFile::new(&playlist))?,
+        ),
+        ExportFormat::Xspf => (
+            "application/xspf+xml",
+            "xspf",
+            playlist_files::to_xspf(&playlist),
+        ),
+        ExportFormat::M3u => ("audio/x-mpegurl", "m3u", playlist_files::to_m3u(&playlist)),
+    };
+
+    let disposition = format!("attachment; filename=\"playlist-{playlist_id}.{extension}\"");
+
+    Ok((
+        [
+            (CONTENT_TYPE, content_type.to_string()),
+            (CONTENT_DISPOSITION, disposition),
+        ],
+        body,
+    ))
+}
+
+#[derive(Deserialize, Clone, Copy, Debug)]
+#[serde(rename_all = "snake_case")]
+enum ImportFormat {
+    Grooves,
+    /// One album id, uri or url per line
+    Csv,
+}
+
+#[derive(Deserialize, Clone, Debug)]
+struct ImportParams {
+    format: ImportFormat,
+    /// Needed for csv imports, and overrides the name in grooves files
+    name: Option<String>,
+}
+
+/// Creates a playlist from a file. Albums go through the same conversion as `album_to_element`.
+/// Album csv entries that can't be converted are reported instead of imported
+async fn import_playlist(
+    State(state): State<AppState>,
+    Extension(current_user): Extension<User>,
+    Query(params): Query<ImportParams>,
+    body: String,
+) -> GroovesResult<impl IntoResponse> {
+    let mut invalid: Vec<InvalidEntry> = Vec::new();
+
+    let (name, elements) = match params.format {
+        ImportFormat::Grooves => {
+            let file: GroovesFile = serde_json::from_str(&body).map_err(|e| {
+                warn!(error = ?e, "invalid grooves file");
+                GroovesError::InvalidRequest
+            })?;
+
+            if !file.is_supported() {
+                warn!(
+                    format = file.format,
+                    version = file.version,
+                    "unsupported grooves file"
+                );
+                return Err(GroovesError::InvalidRequest);
+            }
+
+            // Checked before spotify is asked about the albums, which is slow for big files
+            let name = params.name.unwrap_or(file.name);
+            validate_playlist(&name, &file.elements, &state.limits)?;
+
+            let token = current_user.token.ok_or(GroovesError::Unauthorized)?;
+            let client = spotify::client_with_token(token.clone());
+
+            let elements = normalize_elements(&client, file.elements).await?;
+            spotify::save_refreshed_token(&client, token, current_user.id, &state.db_pool).await?;
+
+            (name, elements)
+        }
+        ImportFormat::Csv => {
+            let name = params.name.ok_or(GroovesError::InvalidRequest)?;
+            let (album_ids, invalid_entries) = playlist_files::parse_album_csv(&body);
+            invalid = invalid_entries;
+
+            let token = current_user.token.ok_or(GroovesError::Unauthorized)?;
+            let client = spotify::client_with_token(token.clone());
+
+            // One at a time, so an album that doesn't exist only fails its own entry
+            let mut elements = Vec::with_capacity(album_ids.len());
+            for (line, album_id) in album_ids {
+                match spotify::fetch_album_elements(&client, std::slice::from_ref(&album_id)).await
+                {
+                    Ok(element) => elements.extend(element),
+                    Err(e) => {
+                        debug!(error = ?e, line, %album_id, "failed to get album");
+                        invalid.push(InvalidEntry {
+                            line,
+                            entry: album_id.to_string(),
+                        });
+                    }
+                }
+            }
+
+            spotify::save_refreshed_token(&client, token, current_user.id, &state.db_pool).await?;
+
+            (name, elements)
+        }
+    };
+    validate_playlist(&name, &elements, &state.limits)?;
+
+    let mut tx = state.db_pool.begin().await?;
+    let playlist = db::create_playlist(&mut tx, name, current_user.id, elements).await?;
+    tx.commit().await?;
+
+    Ok(Json(json!({"playlist": playlist, "invalid": invalid})))
+}
+
+/// Rebuilds imported elements from their albums as spotify has them now, so nothing spotify
+/// decides, like which songs are unavailable, is taken from the file
+async fn normalize_elements(
+    client: &AuthCodeSpotify,
+    elements: Vec<PlaylistElement>,
+) -> GroovesResult<Vec<PlaylistElement>> {
+    let album_ids: Vec<_> = elements
+        .iter()
+        .filter_map(|e| e.album_id.clone())
+        .unique()
+        .collect();
+    let albums = metadata::fetch_albums(client, &album_ids).await?;
+
+    let mut elements: Vec<PlaylistElement> = elements
+        .into_iter()
+        .map(
+            |element| match element.album_id.as_ref().and_then(|id| albums.get(id)) {
+                Some(album) => playlist_files::with_editor_choices(
+                    PlaylistElement::from(album.clone()),
+                    &element,
+                ),
+                None => playlist_files::without_spotify_state(element),
+            },
+        )
+        .collect();
+
+    spotify::fill_isrcs(client, &mut elements).await?;
+
+    Ok(elements)
+}
@@ -0,0 +1,202 @@
+use axum::extract::{Path, State};
+use axum::http::StatusCode;
+use axum::response::IntoResponse;
+use axum::routing::{delete, get, put};
+use axum::{Extension, Json, Router};
+use grooves_model::{PlaylistInvite, PlaylistMember, PlaylistRole, User};
+use serde::Deserialize;
+use sqlx::PgConnection;
+
+use crate::db::playlists as db;
+use crate::error::{GroovesError, GroovesResult};
+use crate::{util, AppState};
+
+pub fn router() -> Router<AppState> {
+    Router::new()
+        .route("/members", get(get_members))
+        .route("/members/:userId", put(update_member).delete(remove_member))
+        .route("/invites", get(get_invites).post(create_invite))
+        .route("/invites/:inviteId", delete(delete_invite))
+}
+
+async fn get_members(
+    State(state): State<AppState>,
+    Extension(current_user): Extension<User>,
+    Path(playlist_id): Path<i32>,
+) -> GroovesResult<impl IntoResponse> {
+    let mut conn = state.db_pool.acquire().await?;
+    db::require_role(
+        &mut conn,
+        playlist_id,
+        current_user.id,
+        PlaylistRole::Viewer,
+    )
+    .await?;
+
+    let members: Vec<PlaylistMember> = sqlx::query_as(
+        r#"SELECT m.playlist_id, m.user_id, u.spotify_id, m.role
+            FROM playlist_member m JOIN "user" u ON m.user_id = u.id
+            WHERE m.playlist_id = $1
+            ORDER BY m.role DESC, m.user_id"#,
+    )
+    .bind(playlist_id)
+    .fetch_all(&mut *conn)
+    .await?;
+
+    Ok(Json(members))
+}
+
+#[derive(Deserialize, Clone, Debug)]
+struct UpdateMember {
+    role: PlaylistRole,
+}
+
+async fn update_member(
+    State(state): State<AppState>,
+    Extension(current_user): Extension<User>,
+    Path((playlist_id, user_id)): Path<(i32, i32)>,
+    Json(payload): Json<UpdateMember>,
+) -> GroovesResult<impl IntoResponse> {
+    let mut tx = state.db_pool.begin().await?;
+    db::lock_playlist(&mut tx, playlist_id, current_user.id, PlaylistRole::Owner).await?;
+    let owner_id = db::current_owner(&mut tx, playlist_id).await?;
+
+    if payload.role != PlaylistRole::Owner {
+        check_not_last_owner(&mut tx, playlist_id, user_id).await?;
+    }
+
+    let res =
+        sqlx::query("UPDATE playlist_member SET role = $1 WHERE playlist_id = $2 AND user_id = $3")
+            .bind(payload.role)
+            .bind(playlist_id)
+            .bind(user_id)
+            .execute(&mut *tx)
+            .await?;
+
+    if res.rows_affected() == 0 {
+        return Err(GroovesError::NotFound);
+    }
+    db::bump_version_if_owner_changed(&mut tx, playlist_id, owner_id).await?;
+    tx.commit().await?;
+
+    Ok(StatusCode::OK)
+}
+
+/// Owners can remove anyone, and anyone can remove themselves to leave the playlist
+async fn remove_member(
+    State(state): State<AppState>,
+    Extension(current_user): Extension<User>,
+    Path((playlist_id, user_id)): Path<(i32, i32)>,
+) -> GroovesResult<impl IntoResponse> {
+    let role = if user_id == current_user.id {
+        PlaylistRole::Viewer
+    } else {
+        PlaylistRole::Owner
+    };
+
+    let mut tx = state.db_pool.begin().await?;
+    db::lock_playlist(&mut tx, playlist_id, current_user.id, role).await?;
+    check_not_last_owner(&mut tx, playlist_id, user_id).await?;
+    let owner_id = db::current_owner(&mut tx, playlist_id).await?;
+
+    let res = sqlx::query("DELETE FROM playlist_member WHERE playlist_id = $1 AND user_id = $2")
+        .bind(playlist_id)
+        .bind(user_id)
+        .execute(&mut *tx)
+        .await?;
+
+    if res.rows_affected() == 0 {
+        return Err(GroovesError::NotFound);
+    }
+    db::bump_version_if_owner_changed(&mut tx, playlist_id, owner_id).await?;
+    tx.commit().await?;
+
+    Ok(StatusCode::OK)
+}
+
+/// Every playlist needs an owner, so the last one can't be removed or demoted
+async fn check_not_last_owner(
+    conn: &mut PgConnection,
+    playlist_id: i32,
+    user_id: i32,
+) -> GroovesResult<()> {
+    let other_owners: i64 = sqlx::query_scalar(
+        r#"SELECT count(*) FROM playlist_member
+            WHERE playlist_id = $1 AND role = 'owner' AND user_id != $2"#,
+    )
+    .bind(playlist_id)
+    .bind(user_id)
+    .fetch_one(conn)
+    .await?;
+
+    if other_owners == 0 {
+        Err(GroovesError::InvalidRequest)
+    } else {
+        Ok(())
+    }
+}
+
+async fn get_invites(
+    State(state): State<AppState>,
+    Extension(current_user): Extension<User>,
+    Path(playlist_id): Path<i32>,
+) -> GroovesResult<impl IntoResponse> {
+    let mut conn = state.db_pool.acquire().await?;
+    db::require_role(&mut conn, playlist_id, current_user.id, PlaylistRole::Owner).await?;
+
+    let invites: Vec<PlaylistInvite> =
+        sqlx::query_as("SELECT * FROM playlist_invite WHERE playlist_id = $1 ORDER BY id")
+            .bind(playlist_id)
+            .fetch_all(&mut *conn)
+            .await?;
+
+    Ok(Json(invites))
+}
+
+#[derive(Deserialize, Clone, Debug)]
+struct CreateInvite {
+    role: PlaylistRole,
+}
+
+async fn create_invite(
+    State(state): State<AppState>,
+    Extension(current_user): Extension<User>,
+    Path(playlist_id): Path<i32>,
+    Json(payload): Json<CreateInvite>,
+) -> GroovesResult<impl IntoResponse> {
+    let mut conn = state.db_pool.acquire().await?;
+    db::require_role(&mut conn, playlist_id, current_user.id, PlaylistRole::Owner).await?;
+
+    let invite: PlaylistInvite = sqlx::query_as(
+        "INSERT INTO playlist_invite (playlist_id, token, role) VALUES ($1, $2, $3) RETURNING *",
+    )
+    .bind(playlist_id)
+    .bind(util::generate_session_token())
+    .bind(payload.role)
+    .fetch_one(&mut *conn)
+    .await?;
+
+    Ok(Json(invite))
+}
+
+/// Revokes an invite so its token can't be accepted anymore. Existing members are kept
+async fn delete_invite(
+    State(state): State<AppState>,
+    Extension(current_user): Extension<User>,
+    Path((playlist_id, invite_id)): Path<(i32, i32)>,
+) -> GroovesResult<impl IntoResponse> {
+    let mut conn = state.db_pool.acquire().await?;
+    db::require_role(&mut conn, playlist_id, current_user.id, PlaylistRole::Owner).await?;
+
+    let res = sqlx::query("DELETE FROM playlist_invite WHERE id = $1 AND playlist_id = $2")
+        .bind(invite_id)
+        .bind(playlist_id)
+        .execute(&mut *conn)
+        .await?;
+
+    if res.rows_affected() == 0 {
+        Err(GroovesError::NotFound)
+    } else {
+        Ok(StatusCode::OK)
+    }
+}
@@ -0,0 +1,148 @@
+use axum::extract::{Path, Query, State};
+use axum::http::header::ETAG;
+use axum::http::HeaderMap;
+use axum::response::IntoResponse;
+use axum::routing::{get, post};
+use axum::{Extension, Json, Router};
+use grooves_model::{
+    diff_elements, Playlist, PlaylistRevision, PlaylistRevisionSummary, PlaylistRole, User,
+};
+use serde::Deserialize;
+use sqlx::PgConnection;
+
+use super::{check_if_match, etag};
+use crate::db::playlists as db;
+use crate::error::{GroovesError, GroovesResult};
+use crate::validation::validate_playlist;
+use crate::AppState;
+
+pub fn router() -> Router<AppState> {
+    Router::new()
+        .route("/", get(get_revisions))
+        .route("/diff", get(diff_revisions))
+        .route("/:revisionId", get(get_revision))
+        .route("/:revisionId/restore", post(restore_revision))
+}
+
+async fn get_revisions(
+    State(state): State<AppState>,
+    Extension(current_user): Extension<User>,
+    Path(playlist_id): Path<i32>,
+) -> GroovesResult<impl IntoResponse> {
+    let mut conn = state.db_pool.acquire().await?;
+    db::require_role(
+        &mut conn,
+        playlist_id,
+        current_user.id,
+        PlaylistRole::Viewer,
+    )
+    .await?;
+
+    let revisions: Vec<PlaylistRevisionSummary> = sqlx::query_as(
+        r#"SELECT id, playlist_id, author_id, name, created_at,
+                jsonb_array_length(elements) AS element_count
+            FROM playlist_revision
+            WHERE playlist_id = $1
+            ORDER BY id DESC"#,
+    )
+    .bind(playlist_id)
+    .fetch_all(&mut *conn)
+    .await?;
+
+    Ok(Json(revisions))
+}
+
+async fn get_revision(
+    State(state): State<AppState>,
+    Extension(current_user): Extension<User>,
+    Path((playlist_id, revision_id)): Path<(i32, i32)>,
+) -> GroovesResult<impl IntoResponse> {
+    let mut conn = state.db_pool.acquire().await?;
+    db::require_role(
+        &mut conn,
+        playlist_id,
+        current_user.id,
+        PlaylistRole::Viewer,
+    )
+    .await?;
+    let revision = fetch_revision(&mut conn, playlist_id, revision_id).await?;
+
+    Ok(Json(revision))
+}
+
+#[derive(Deserialize, Clone, Debug)]
+struct DiffParams {
+    from: i32,
+    to: i32,
+}
+
+async fn diff_revisions(
+    State(state): State<AppState>,
+    Extension(current_user): Extension<User>,
+    Path(playlist_id): Path<i32>,
+    Query(params): Query<DiffParams>,
+) -> GroovesResult<impl IntoResponse> {
+    let mut conn = state.db_pool.acquire().await?;
+    db::require_role(
+        &mut conn,
+        playlist_id,
+        current_user.id,
+        PlaylistRole::Viewer,
+    )
+    .await?;
+    let from = fetch_revision(&mut conn, playlist_id, params.from).await?;
+    let to = fetch_revision(&mut conn, playlist_id, params.to).await?;
+
+    Ok(Json(diff_elements(&from.elements, &to.elements)))
+}
+
+/// Puts the playlist back the way it was at a revision. This is saved as a new revision
+async fn restore_revision(
+    State(state): State<AppState>,
+    Extension(current_user): Extension<User>,
+    Path((playlist_id, revision_id)): Path<(i32, i32)>,
+    headers: HeaderMap,
+) -> GroovesResult<impl IntoResponse> {
+    let mut tx = state.db_pool.begin().await?;
+
+    let playlist =
+        db::lock_playlist(&mut tx, playlist_id, current_user.id, PlaylistRole::Editor).await?;
+    check_if_match(&headers, playlist.version)?;
+    db::require_not_smart(&mut tx, playlist_id).await?;
+
+    let revision = fetch_revision(&mut tx, playlist_id, revision_id).await?;
+    // The revision might be from before the limits were what they are now
+    validate_playlist(&revision.name, &revision.elements, &state.limits)?;
+
+    let mut playlist: Playlist = sqlx::query_as(
+        "UPDATE playlist SET name = $1, version = version + 1 WHERE id = $2 RETURNING *",
+    )
+    .bind(revision.name)
+    .bind(playlist_id)
+    .fetch_one(&mut *tx)
+    .await?;
+
+    db::replace_elements(&mut tx, playlist_id, &revision.elements).await?;
+    playlist.elements = revision.elements;
+
+    db::record_revision(&mut tx, &playlist, current_user.id).await?;
+    tx.commit().await?;
+
+    Ok(([(ETAG, etag(playlist.version))], Json(playlist)))
+}
+
+async fn fetch_revision(
+    conn: &mut PgConnection,
+    playlist_id: i32,
+    revision_id: i32,
+) -> GroovesResult<PlaylistRevision> {
+    let revision =
+        sqlx::query_as("SELECT * FROM playlist_revision WHERE id = $1 AND playlist_id = $2")
+            .bind(revision_id)
+            .bind(playlist_id)
+            .fetch_optional(conn)
+            .await?
+            .ok_or(GroovesError::NotFound)?;
+
+    Ok(revision)
+}
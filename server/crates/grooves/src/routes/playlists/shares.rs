@@ -0,0 +1,77 @@
+use axum::extract::{Path, State};
+use axum::http::StatusCode;
+use axum::response::IntoResponse;
+use axum::routing::{delete, get};
+use axum::{Extension, Json, Router};
+use grooves_model::{PlaylistRole, PlaylistShare, User};
+
+use crate::db::playlists as db;
+use crate::error::{GroovesError, GroovesResult};
+use crate::{util, AppState};
+
+pub fn router() -> Router<AppState> {
+    Router::new()
+        .route("/", get(get_shares).post(create_share))
+        .route("/:shareId", delete(delete_share))
+}
+
+async fn get_shares(
+    State(state): State<AppState>,
+    Extension(current_user): Extension<User>,
+    Path(playlist_id): Path<i32>,
+) -> GroovesResult<impl IntoResponse> {
+    let mut conn = state.db_pool.acquire().await?;
+    db::require_role(&mut conn, playlist_id, current_user.id, PlaylistRole::Owner).await?;
+
+    let shares: Vec<PlaylistShare> =
+        sqlx::query_as("SELECT * FROM playlist_share WHERE playlist_id = $1 ORDER BY id")
+            .bind(playlist_id)
+            .fetch_all(&mut *conn)
+            .await?;
+
+    Ok(Json(shares))
+}
+
+async fn create_share(
+    State(state): State<AppState>,
+    Extension(current_user): Extension<User>,
+    Path(playlist_id): Path<i32>,
+) -> GroovesResult<impl IntoResponse> {
+    let mut conn = state.db_pool.acquire().await?;
+    db::require_role(&mut conn, playlist_id, current_user.id, PlaylistRole::Owner).await?;
+    // What a smart playlist has depends on who's looking at it, and shares can be viewed by
+    // anyone
+    db::require_not_smart(&mut conn, playlist_id).await?;
+
+    let share: PlaylistShare = sqlx::query_as(
+        "INSERT INTO playlist_share (playlist_id, token) VALUES ($1, $2) RETURNING *",
+    )
+    .bind(playlist_id)
+    .bind(util::generate_session_token())
+    .fetch_one(&mut *conn)
+    .await?;
+
+    Ok(Json(share))
+}
+
+/// Revokes a share so its token stops working
+async fn delete_share(
+    State(state): State<AppState>,
+    Extension(current_user): Extension<User>,
+    Path((playlist_id, share_id)): Path<(i32, i32)>,
+) -> GroovesResult<impl IntoResponse> {
+    let mut conn = state.db_pool.acquire().await?;
+    db::require_role(&mut conn, playlist_id, current_user.id, PlaylistRole::Owner).await?;
+
+    let res = sqlx::query("DELETE FROM playlist_share WHERE id = $1 AND playlist_id = $2")
+        .bind(share_id)
+        .bind(playlist_id)
+        .execute(&mut *conn)
+        .await?;
+
+    if res.rows_affected() == 0 {
+        Err(GroovesError::NotFound)
+    } else {
+        Ok(StatusCode::OK)
+    }
+}
@@ -0,0 +1,125 @@
+use axum::extract::{Path, State};
+use axum::http::header::ETAG;
+use axum::http::HeaderMap;
+use axum::response::IntoResponse;
+use axum::routing::{get, post};
+use axum::{Extension, Json, Router};
+use grooves_model::{PlaylistRole, SmartRules, User};
+use serde::Deserialize;
+
+use super::{check_if_match, etag};
+use crate::db::playlists as db;
+use crate::error::{GroovesError, GroovesResult};
+use crate::validation::{JsonBody, Validator};
+use crate::{smart, AppState};
+
+pub fn router() -> Router<AppState> {
+    Router::new()
+        .route("/smart", post(create_smart_playlist))
+        .route("/:playlistId/rules", get(get_rules).put(update_rules))
+        .route("/:playlistId/preview", get(preview))
+}
+
+#[derive(Deserialize, Clone, Debug)]
+struct CreateSmartPlaylist {
+    name: String,
+    rules: SmartRules,
+}
+
+/// Creates a playlist without elements of its own. They come from its rules when it's played
+async fn create_smart_playlist(
+    State(state): State<AppState>,
+    Extension(current_user): Extension<User>,
+    JsonBody(payload): JsonBody<CreateSmartPlaylist>,
+) -> GroovesResult<impl IntoResponse> {
+    let mut validator = Validator::default();
+    validator.name("name", &payload.name, &state.limits);
+    validator.smart_rules("rules", &payload.rules);
+    validator.finish()?;
+
+    let mut tx = state.db_pool.begin().await?;
+    let playlist = db::create_playlist(&mut tx, payload.name, current_user.id, vec![]).await?;
+
+    db::insert_smart_rules(&mut tx, playlist.id, &payload.rules).await?;
+    tx.commit().await?;
+
+    Ok(([(ETAG, etag(playlist.version))], Json(playlist)))
+}
+
+async fn get_rules(
+    State(state): State<AppState>,
+    Extension(current_user): Extension<User>,
+    Path(playlist_id): Path<i32>,
+) -> GroovesResult<impl IntoResponse> {
+    let mut conn = state.db_pool.acquire().await?;
+    db::require_role(
+        &mut conn,
+        playlist_id,
+        current_user.id,
+        PlaylistRole::Viewer,
+    )
+    .await?;
+
+    let rules = db::smart_rules(&mut conn, playlist_id)
+        .await?
+        .ok_or(GroovesError::NotFound)?;
+
+    Ok(Json(rules))
+}
+
+async fn update_rules(
+    State(state): State<AppState>,
+    Extension(current_user): Extension<User>,
+    Path(playlist_id): Path<i32>,
+    headers: HeaderMap,
+    JsonBody(rules): JsonBody<SmartRules>,
+) -> GroovesResult<impl IntoResponse> {
+    let mut validator = Validator::default();
+    validator.smart_rules("", &rules);
+    validator.finish()?;
+
+    let mut tx = state.db_pool.begin().await?;
+
+    let playlist =
+        db::lock_playlist(&mut tx, playlist_id, current_user.id, PlaylistRole::Editor).await?;
+    check_if_match(&headers, playlist.version)?;
+
+    let res = sqlx::query("UPDATE smart_playlist SET rules = $2 WHERE playlist_id = $1")
+        .bind(playlist_id)
+        .bind(sqlx::types::Json(&rules))
+        .execute(&mut *tx)
+        .await?;
+    if res.rows_affected() == 0 {
+        return Err(GroovesError::NotFound);
+    }
+
+    let version = db::bump_version(&mut tx, playlist_id).await?;
+    tx.commit().await?;
+
+    Ok(([(ETAG, etag(version))], Json(rules)))
+}
+
+/// The elements the playlist would play right now
+async fn preview(
+    State(state): State<AppState>,
+    Extension(current_user): Extension<User>,
+    Path(playlist_id): Path<i32>,
+) -> GroovesResult<impl IntoResponse> {
+    let mut conn = state.db_pool.acquire().await?;
+    db::require_role(
+        &mut conn,
+        playlist_id,
+        current_user.id,
+        PlaylistRole::Viewer,
+    )
+    .await?;
+
+    let rules = db::smart_rules(&mut conn, playlist_id)
+        .await?
+        .ok_or(GroovesError::NotFound)?;
+    drop(conn);
+
+    let elements = smart::materialize(&state.db_pool, &current_user, &rules).await?;
+
+    Ok(Json(elements))
+}
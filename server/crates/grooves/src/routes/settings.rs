@@ -0,0 +1,42 @@
+use axum::extract::State;
+use axum::response::IntoResponse;
+use axum::routing::get;
+use axum::{Extension, Json, Router};
+use grooves_model::{User, UserSettings};
+use tracing::info;
+
+use crate::error::GroovesResult;
+use crate::{middleware, AppState};
+
+pub fn router(state: AppState) -> Router<AppState> {
+    info!("Creating settings routes");
+
+    Router::new()
+        .route("/", get(get_settings).put(update_settings))
+        .route_layer(axum::middleware::from_fn_with_state(
+            state,
+            middleware::auth::auth,
+        ))
+}
+
+async fn get_settings(Extension(current_user): Extension<User>) -> impl IntoResponse {
+    Json(UserSettings {
+        explicit_filter: current_user.explicit_filter,
+    })
+}
+
+async fn update_settings(
+    State(state): State<AppState>,
+    Extension(current_user): Extension<User>,
+    Json(payload): Json<UserSettings>,
+) -> GroovesResult<impl IntoResponse> {
+    let settings: UserSettings = sqlx::query_as(
+        r#"UPDATE "user" SET explicit_filter = $2 WHERE id = $1 RETURNING explicit_filter"#,
+    )
+    .bind(current_user.id)
+    .bind(payload.explicit_filter)
+    .fetch_one(&state.db_pool)
+    .await?;
+
+    Ok(Json(settings))
+}
@@ -0,0 +1,72 @@
+use axum::extract::{Path, State};
+use axum::response::IntoResponse;
+use axum::routing::{get, post};
+use axum::{Extension, Json, Router};
+use grooves_model::{Playlist, User};
+use sqlx::PgConnection;
+use tracing::info;
+
+use crate::db::playlists as db;
+use crate::error::{GroovesError, GroovesResult};
+use crate::validation::validate_playlist;
+use crate::{middleware, AppState};
+
+pub fn router(state: AppState) -> Router<AppState> {
+    info!("Creating shared playlist routes");
+
+    // Viewing a shared playlist doesn't need an account, copying it does
+    Router::new()
+        .route("/:token", get(get_shared_playlist))
+        .route(
+            "/:token/copy",
+            post(copy_shared_playlist).route_layer(axum::middleware::from_fn_with_state(
+                state,
+                middleware::auth::auth,
+            )),
+        )
+}
+
+async fn get_shared_playlist(
+    State(state): State<AppState>,
+    Path(token): Path<String>,
+) -> GroovesResult<impl IntoResponse> {
+    let mut conn = state.db_pool.acquire().await?;
+    let playlist = fetch_shared_playlist(&mut conn, &token).await?;
+
+    Ok(Json(playlist))
+}
+
+/// Saves a copy of a shared playlist to the current user's playlists
+async fn copy_shared_playlist(
+    State(state): State<AppState>,
+    Extension(current_user): Extension<User>,
+    Path(token): Path<String>,
+) -> GroovesResult<impl IntoResponse> {
+    let mut tx = state.db_pool.begin().await?;
+
+    let shared = fetch_shared_playlist(&mut tx, &token).await?;
+    validate_playlist(&shared.name, &shared.elements, &state.limits)?;
+    let playlist =
+        db::create_playlist(&mut tx, shared.name, current_user.id, shared.elements).await?;
+
+    tx.commit().await?;
+
+    Ok(Json(playlist))
+}
+
+async fn fetch_shared_playlist(conn: &mut PgConnection, token: &str) -> GroovesResult<Playlist> {
+    let mut playlist: Playlist = sqlx::query_as(
+        "SELECT p.* FROM playlist p JOIN playlist_share s ON s.playlist_id = p.id WHERE s.token = $1",
+    )
+    .bind(token)
+    .fetch_optional(&mut *conn)
+    .await?
+    .ok_or(GroovesError::NotFound)?;
+
+    // Smart playlists can't be shared anymore, but shares of them might already exist
+    db::require_not_smart(conn, playlist.id).await?;
+
+    db::load_elements(conn, std::slice::from_mut(&mut playlist)).await?;
+
+    Ok(playlist)
+}
@@ -3,20 +3,27 @@ use std::collections::HashMap;
 use anyhow::anyhow;
 use axum::extract::{Path, Query, State};
 use axum::response::IntoResponse;
-use axum::routing::get;
+use axum::routing::{get, post};
 use axum::{Extension, Json, Router};
 use axum_macros::debug_handler;
-use grooves_model::{PlaylistElement, Song, User};
+use chrono::{DateTime, Utc};
+use grooves_model::{Playlist, PlaylistElement, PlaylistRole, User};
+use grooves_player::player::element_order;
 use itertools::Itertools;
-use rspotify::model::{AlbumId, SearchResult, SearchType};
-use rspotify::prelude::{BaseClient, Id};
-use serde::Serialize;
+use rspotify::model::{
+    AlbumId, FullAlbum, PlayableId, PlayableItem, PlaylistId, SearchResult, SearchType, TrackId,
+};
+use rspotify::prelude::{BaseClient, Id, OAuthClient};
+use serde::{Deserialize, Serialize};
 use serde_json::json;
+use sqlx::PgConnection;
 use tracing::{debug, info};
 
+use crate::db::playlists as db;
 use crate::error::{GroovesError, GroovesResult};
 use crate::util::spotify;
-use crate::{middleware, AppState};
+use crate::validation::{validate_playlist, Validator};
+use crate::{middleware, smart, AppState};
 
 pub fn router(state: AppState) -> Router<AppState> {
     info!("Creating spotify api routes");
@@ -24,6 +31,9 @@ pub fn router(state: AppState) -> Router<AppState> {
     Router::new()
         .route("/search", get(search))
         .route("/album_to_element/:album_id", get(album_to_element))
+        .route("/import/playlist/:playlist_id", post(import_playlist))
+        .route("/import/library", post(import_library))
+        .route("/export/:playlistId", post(export_playlist))
         .route_layer(axum::middleware::from_fn_with_state(
             state,
             middleware::auth::auth,
@@ -67,7 +77,7 @@ async fn search(
         .map(|song| SearchResponse {
             name: song.name.clone(),
             spotify_id: song.id.as_ref().unwrap().id().to_string(),
-            image_url: get_min_image_url(&song.album.images)
+            image_url: spotify::get_min_image_url(&song.album.images)
                 .unwrap_or("")
                 .to_string(),
         })
@@ -89,18 +99,13 @@ async fn search(
         .map(|album| SearchResponse {
             name: album.name.clone(),
             spotify_id: album.id.as_ref().unwrap().id().to_string(),
-            image_url: get_min_image_url(&album.images).unwrap_or("").to_string(),
+            image_url: spotify::get_min_image_url(&album.images)
+                .unwrap_or("")
+                .to_string(),
         })
         .collect();
 
-    let new_token = client.get_token().lock().await.unwrap().clone();
-    if new_token != Some(token) {
-        sqlx::query(r#"UPDATE "user" SET token = $1 WHERE id = $2"#)
-            .bind(sqlx::types::Json(new_token))
-            .bind(current_user.id)
-            .execute(&state.db_pool)
-            .await?;
-    }
+    spotify::save_refreshed_token(&client, token, current_user.id, &state.db_pool).await?;
 
     Ok(Json(json!({"songs": songs, "albums": albums})))
 }
@@ -117,50 +122,436 @@ async fn album_to_element(
 
     debug!(album_id, "getting album from spotify");
     let album = client.album(AlbumId::from_id(album_id)?, None).await?;
-    let image_url = get_max_image_url(&album.images).unwrap_or("");
-    let artists = album.artists.iter().map(|a| &a.name).join(", ");
 
-    let songs: Vec<Song> = album
-        .tracks
-        .items
-        .iter()
-        .map(|s| Song {
-            name: s.name.clone(),
-            image_url: image_url.to_string(),
-            artists: artists.clone(),
-            spotify_id: s.id.as_ref().unwrap().clone(),
-        })
-        .collect();
+    let mut element = PlaylistElement::from(album);
+    spotify::fill_isrcs(&client, std::slice::from_mut(&mut element)).await?;
+
+    spotify::save_refreshed_token(&client, token, current_user.id, &state.db_pool).await?;
+
+    Ok(Json(element))
+}
+
+/// The most playlist items spotify will return from a single request
+const PLAYLIST_ITEMS_PER_REQUEST: u32 = 100;
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "snake_case")]
+enum UnmappedReason {
+    /// Local files aren't on spotify so they have no album to look up
+    LocalFile,
+    Episode,
+    /// The track was removed from spotify or isn't available in the user's country
+    Unavailable,
+}
+
+#[derive(Debug, Serialize)]
+struct UnmappedTrack {
+    /// Where the track is in the spotify playlist
+    position: usize,
+    name: Option<String>,
+    reason: UnmappedReason,
+}
+
+/// Creates a playlist with one element for each album that has tracks in a spotify playlist.
+/// Elements are in the order their albums first appear
+#[debug_handler]
+async fn import_playlist(
+    State(state): State<AppState>,
+    Extension(current_user): Extension<User>,
+    Path(playlist_id): Path<String>,
+) -> GroovesResult<impl IntoResponse> {
+    let token = current_user.token.ok_or(GroovesError::Unauthorized)?;
+
+    let client = spotify::client_with_token(token.clone());
+    let playlist_id = PlaylistId::from_id_or_uri(&playlist_id)
+        .map_err(|_| GroovesError::InvalidRequest)?
+        .into_static();
+
+    debug!(%playlist_id, "getting playlist from spotify");
+    let spotify_playlist = client.playlist(playlist_id.clone(), None, None).await?;
+
+    let mut album_ids = Vec::new();
+    let mut unmapped = Vec::new();
+    let mut offset = 0;
+    loop {
+        let page = client
+            .playlist_items_manual(
+                playlist_id.clone(),
+                None,
+                None,
+                Some(PLAYLIST_ITEMS_PER_REQUEST),
+                Some(offset),
+            )
+            .await?;
+
+        for (i, item) in page.items.into_iter().enumerate() {
+            let position = offset as usize + i;
+            let (name, reason) = match item.track {
+                Some(PlayableItem::Track(track)) if item.is_local => {
+                    (Some(track.name), UnmappedReason::LocalFile)
+                }
+                Some(PlayableItem::Track(track)) => match track.album.id {
+                    Some(album_id) => {
+                        album_ids.push(album_id);
+                        continue;
+                    }
+                    None => (Some(track.name), UnmappedReason::Unavailable),
+                },
+                Some(PlayableItem::Episode(episode)) => {
+                    (Some(episode.name), UnmappedReason::Episode)
+                }
+                None => (None, UnmappedReason::Unavailable),
+            };
+
+            unmapped.push(UnmappedTrack {
+                position,
+                name,
+                reason,
+            });
+        }
+
+        if page.next.is_none() {
+            break;
+        }
+        offset += PLAYLIST_ITEMS_PER_REQUEST;
+    }
+
+    let album_ids: Vec<AlbumId<'static>> = album_ids.into_iter().unique().collect();
+    let elements = spotify::fetch_album_elements(&client, &album_ids).await?;
+
+    spotify::save_refreshed_token(&client, token, current_user.id, &state.db_pool).await?;
+    validate_playlist(&spotify_playlist.name, &elements, &state.limits)?;
+
+    let mut tx = state.db_pool.begin().await?;
+    let playlist =
+        db::create_playlist(&mut tx, spotify_playlist.name, current_user.id, elements).await?;
+    tx.commit().await?;
+
+    Ok(Json(json!({"playlist": playlist, "unmapped": unmapped})))
+}
+
+const DEFAULT_LIBRARY_PLAYLIST_NAME: &str = "Saved albums";
+
+#[derive(Deserialize, Clone, Debug)]
+struct ImportLibrary {
+    /// The playlist to update, or None to create a new one
+    playlist_id: Option<i32>,
+    name: Option<String>,
+    added_after: Option<DateTime<Utc>>,
+    /// Only albums by an artist whose name contains this, ignoring case
+    artist: Option<String>,
+    release_year: Option<i32>,
+    /// Only add albums saved since the playlist was last synced instead of replacing its
+    /// elements. Needs `playlist_id`
+    #[serde(default)]
+    incremental: bool,
+}
+
+impl ImportLibrary {
+    fn matches(&self, album: &FullAlbum) -> bool {
+        let artist_matches = self.artist.as_ref().is_none_or(|artist| {
+            let artist = artist.to_lowercase();
+            album
+                .artists
+                .iter()
+                .any(|a| a.name.to_lowercase().contains(&artist))
+        });
+
+        // Release dates are "YYYY", "YYYY-MM" or "YYYY-MM-DD" depending on their precision
+        let year_matches = self.release_year.is_none_or(|year| {
+            album.release_date.get(..4).and_then(|y| y.parse().ok()) == Some(year)
+        });
+
+        artist_matches && year_matches
+    }
+}
+
+/// Creates or updates a playlist with one element for each album in the user's library,
+/// most recently saved first
+#[debug_handler]
+async fn import_library(
+    State(state): State<AppState>,
+    Extension(current_user): Extension<User>,
+    Json(payload): Json<ImportLibrary>,
+) -> GroovesResult<impl IntoResponse> {
+    if payload.incremental && payload.playlist_id.is_none() {
+        return Err(GroovesError::InvalidRequest);
+    }
+
+    let mut conn = state.db_pool.acquire().await?;
+    let last_synced_at: Option<DateTime<Utc>> = match payload.playlist_id {
+        Some(playlist_id) => {
+            db::require_role(
+                &mut conn,
+                playlist_id,
+                current_user.id,
+                PlaylistRole::Editor,
+            )
+            .await?;
+
+            sqlx::query_scalar("SELECT synced_at FROM library_sync WHERE playlist_id = $1")
+                .bind(playlist_id)
+                .fetch_optional(&mut *conn)
+                .await?
+        }
+        None => None,
+    };
+    drop(conn);
+
+    // Saved albums come newest first, so paging can stop once they're older than this
+    let cutoff = if payload.incremental {
+        payload.added_after.max(last_synced_at)
+    } else {
+        payload.added_after
+    };
+
+    let token = current_user.token.ok_or(GroovesError::Unauthorized)?;
+    let client = spotify::client_with_token(token.clone());
 
-    let new_token = client.get_token().lock().await.unwrap().clone();
-    if new_token != Some(token) {
-        sqlx::query(r#"UPDATE "user" SET token = $1 WHERE id = $2"#)
-            .bind(sqlx::types::Json(new_token))
-            .bind(current_user.id)
-            .execute(&state.db_pool)
+    debug!(?cutoff, "getting saved albums from spotify");
+    let mut elements = Vec::new();
+    let mut newest_saved_at = None;
+    let mut offset = 0;
+    'pages: loop {
+        let page = client
+            .current_user_saved_albums_manual(
+                None,
+                Some(spotify::SAVED_ALBUMS_PER_REQUEST),
+                Some(offset),
+            )
             .await?;
+
+        for saved in page.items {
+            newest_saved_at = newest_saved_at.max(Some(saved.added_at));
+
+            if cutoff.is_some_and(|cutoff| saved.added_at <= cutoff) {
+                break 'pages;
+            }
+
+            if payload.matches(&saved.album) {
+                elements.push(PlaylistElement::from(saved.album));
+            }
+        }
+
+        if page.next.is_none() {
+            break;
+        }
+        offset += spotify::SAVED_ALBUMS_PER_REQUEST;
     }
 
-    let response: PlaylistElement = PlaylistElement {
-        name: album.name,
-        artists,
-        image_url: image_url.to_string(),
-        songs,
+    spotify::fill_isrcs(&client, &mut elements).await?;
+    spotify::save_refreshed_token(&client, token, current_user.id, &state.db_pool).await?;
+
+    let mut added = elements.len();
+    let mut tx = state.db_pool.begin().await?;
+
+    let playlist = match payload.playlist_id {
+        Some(playlist_id) => {
+            let mut playlist =
+                db::lock_playlist(&mut tx, playlist_id, current_user.id, PlaylistRole::Editor)
+                    .await?;
+            db::require_not_smart(&mut tx, playlist_id).await?;
+
+            let mut validator = Validator::default();
+            if payload.incremental {
+                let len = db::count_elements(&mut tx, playlist_id).await?;
+                let existing_album_ids = db::album_ids(&mut tx, playlist_id).await?;
+
+                // Albums that were saved again or moved in the library are already there
+                elements.retain(|e| {
+                    e.album_id
+                        .as_ref()
+                        .is_none_or(|id| !existing_album_ids.contains(id))
+                });
+                added = elements.len();
+
+                validator.elements(
+                    "elements",
+                    &elements,
+                    len as usize,
+                    &existing_album_ids,
+                    &state.limits,
+                );
+                validator.finish()?;
+
+                db::insert_elements(&mut tx, playlist_id, len, &elements).await?;
+            } else {
+                validator.elements("elements", &elements, 0, &[], &state.limits);
+                validator.finish()?;
+
+                db::replace_elements(&mut tx, playlist_id, &elements).await?;
+            }
+
+            playlist.version = db::bump_version(&mut tx, playlist_id).await?;
+            db::load_elements(&mut tx, std::slice::from_mut(&mut playlist)).await?;
+            db::record_revision(&mut tx, &playlist, current_user.id).await?;
+            playlist
+        }
+        None => {
+            let name = payload
+                .name
+                .unwrap_or_else(|| DEFAULT_LIBRARY_PLAYLIST_NAME.to_string());
+            validate_playlist(&name, &elements, &state.limits)?;
+            db::create_playlist(&mut tx, name, current_user.id, elements).await?
+        }
     };
 
-    Ok(Json(response))
+    record_library_sync(
+        &mut tx,
+        &playlist,
+        current_user.id,
+        newest_saved_at.max(last_synced_at),
+    )
+    .await?;
+    tx.commit().await?;
+
+    Ok(Json(json!({"playlist": playlist, "added": added})))
 }
 
-fn get_min_image_url(images: &[rspotify::model::Image]) -> Option<&str> {
-    images
-        .iter()
-        .min_by_key(|a| a.height.unwrap_or(0))
-        .map(|img| &*img.url)
+async fn record_library_sync(
+    conn: &mut PgConnection,
+    playlist: &Playlist,
+    user_id: i32,
+    synced_at: Option<DateTime<Utc>>,
+) -> GroovesResult<()> {
+    let Some(synced_at) = synced_at else {
+        return Ok(());
+    };
+
+    sqlx::query(
+        r#"INSERT INTO library_sync (playlist_id, user_id, synced_at) VALUES ($1, $2, $3)
+            ON CONFLICT (playlist_id) DO UPDATE SET user_id = $2, synced_at = $3"#,
+    )
+    .bind(playlist.id)
+    .bind(user_id)
+    .bind(synced_at)
+    .execute(conn)
+    .await?;
+
+    Ok(())
 }
 
-fn get_max_image_url(images: &[rspotify::model::Image]) -> Option<&str> {
-    images
-        .iter()
-        .max_by_key(|a| a.height.unwrap_or(0))
-        .map(|img| &*img.url)
+/// The most items spotify will add to a playlist in a single request
+const PLAYLIST_ITEMS_PER_WRITE: usize = 100;
+
+#[derive(Deserialize, Clone, Copy, Debug, Default)]
+#[serde(rename_all = "snake_case")]
+enum ExportOrder {
+    /// The elements in the order they are in the playlist
+    #[default]
+    Editor,
+    /// The elements shuffled the same way the player would with the seed.
+    /// Songs stay in order within their element
+    Shuffled,
+}
+
+#[derive(Deserialize, Clone, Debug)]
+struct ExportPlaylist {
+    /// The spotify playlist to overwrite, or None to create a new one
+    spotify_playlist_id: Option<String>,
+    #[serde(default)]
+    public: bool,
+    #[serde(default)]
+    order: ExportOrder,
+    /// A random seed is used if this is None
+    seed: Option<u32>,
+}
+
+/// Puts the songs of a playlist into a spotify playlist so they can be played without grooves
+#[debug_handler]
+async fn export_playlist(
+    State(state): State<AppState>,
+    Extension(current_user): Extension<User>,
+    Path(playlist_id): Path<i32>,
+    Json(payload): Json<ExportPlaylist>,
+) -> GroovesResult<impl IntoResponse> {
+    let mut conn = state.db_pool.acquire().await?;
+    db::require_role(
+        &mut conn,
+        playlist_id,
+        current_user.id,
+        PlaylistRole::Viewer,
+    )
+    .await?;
+
+    let mut playlist: Playlist = sqlx::query_as("SELECT * FROM playlist WHERE id = $1")
+        .bind(playlist_id)
+        .fetch_one(&mut *conn)
+        .await?;
+    drop(conn);
+    smart::load_elements(&state.db_pool, &current_user, &mut playlist).await?;
+
+    // The seed is returned so the same order can be exported or played again
+    let (order, seed): (Vec<usize>, Option<u32>) = match payload.order {
+        ExportOrder::Editor => ((0..playlist.elements.len()).collect(), None),
+        ExportOrder::Shuffled => {
+            let seed = payload.seed.unwrap_or_else(rand::random);
+            (element_order(&playlist.elements, None, seed), Some(seed))
+        }
+    };
+
+    let tracks: Vec<TrackId<'static>> = order
+        .into_iter()
+        .flat_map(|i| &playlist.elements[i].songs)
+        .filter(|s| s.is_playable())
+        .map(|s| s.spotify_id.clone())
+        .collect();
+
+    // Replacing an existing playlist's items with nothing would only lose whatever it had
+    if tracks.is_empty() && payload.spotify_playlist_id.is_some() {
+        let mut validator = Validator::default();
+        validator.error(
+            "spotify_playlist_id",
+            "can't be overwritten with a playlist that has nothing to play",
+        );
+        validator.finish()?;
+    }
+
+    let token = current_user.token.ok_or(GroovesError::Unauthorized)?;
+    let client = spotify::client_with_token(token.clone());
+
+    let spotify_playlist_id = match payload.spotify_playlist_id {
+        Some(id) => PlaylistId::from_id_or_uri(&id)
+            .map_err(|_| GroovesError::InvalidRequest)?
+            .into_static(),
+        None => {
+            let me = client.me().await?;
+            client
+                .user_playlist_create(me.id, &playlist.name, Some(payload.public), None, None)
+                .await?
+                .id
+        }
+    };
+
+    debug!(%spotify_playlist_id, tracks = tracks.len(), "exporting playlist to spotify");
+
+    // Replacing clears the playlist, and only takes one request's worth of items
+    let mut chunks = tracks.chunks(PLAYLIST_ITEMS_PER_WRITE);
+    client
+        .playlist_replace_items(
+            spotify_playlist_id.clone(),
+            chunks
+                .next()
+                .unwrap_or_default()
+                .iter()
+                .map(|t| PlayableId::Track(t.clone())),
+        )
+        .await?;
+    for chunk in chunks {
+        client
+            .playlist_add_items(
+                spotify_playlist_id.clone(),
+                chunk.iter().map(|t| PlayableId::Track(t.clone())),
+                None,
+            )
+            .await?;
+    }
+
+    spotify::save_refreshed_token(&client, token, current_user.id, &state.db_pool).await?;
+
+    Ok(Json(json!({
+        "spotify_playlist_id": spotify_playlist_id.id(),
+        "tracks": tracks.len(),
+        "seed": seed,
+    })))
 }
@@ -0,0 +1,147 @@
+use std::collections::{HashMap, HashSet};
+
+use chrono::{DateTime, Utc};
+use grooves_model::{Playlist, PlaylistElement, PlaylistRole, SmartRules, SmartSource, User};
+use rspotify::prelude::Id;
+use sqlx::{PgConnection, PgPool};
+
+use crate::db::playlists as db;
+use crate::error::{GroovesError, GroovesResult};
+use crate::util::spotify;
+
+/// Fills in the elements of a playlist loaded from the playlist table, working them out from
+/// its rules if it's a smart playlist. Returns whether it was one
+pub async fn load_elements(
+    db_pool: &PgPool,
+    user: &User,
+    playlist: &mut Playlist,
+) -> GroovesResult<bool> {
+    let mut conn = db_pool.acquire().await?;
+
+    match db::smart_rules(&mut conn, playlist.id).await? {
+        Some(rules) => {
+            drop(conn);
+            playlist.elements = materialize(db_pool, user, &rules).await?;
+            Ok(true)
+        }
+        None => {
+            db::load_elements(&mut conn, std::slice::from_mut(playlist)).await?;
+            Ok(false)
+        }
+    }
+}
+
+/// Works out the elements of a smart playlist for the user playing it
+pub async fn materialize(
+    db_pool: &PgPool,
+    user: &User,
+    rules: &SmartRules,
+) -> GroovesResult<Vec<PlaylistElement>> {
+    let elements = match &rules.source {
+        SmartSource::Playlists { playlist_ids } => {
+            let mut conn = db_pool.acquire().await?;
+            playlist_elements(&mut conn, user.id, playlist_ids).await?
+        }
+        SmartSource::Library => {
+            let token = user.token.clone().ok_or(GroovesError::Unauthorized)?;
+            let client = spotify::client_with_token(token.clone());
+
+            let elements = spotify::fetch_saved_album_elements(&client).await?;
+            spotify::save_refreshed_token(&client, token, user.id, db_pool).await?;
+            elements
+        }
+    };
+
+    let last_played = if rules.needs_play_history() {
+        last_played(db_pool, user.id).await?
+    } else {
+        HashMap::new()
+    };
+
+    let now = Utc::now();
+    let mut seen_album_ids = HashSet::new();
+
+    Ok(elements
+        .into_iter()
+        .filter(|e| {
+            e.album_id
+                .as_ref()
+                .is_none_or(|id| seen_album_ids.insert(id.clone()))
+        })
+        .filter(|e| {
+            let played_at = e
+                .album_id
+                .as_ref()
+                .and_then(|id| last_played.get(id.id()))
+                .copied();
+            rules.matches(e, played_at, now)
+        })
+        .take(rules.limit.unwrap_or(usize::MAX))
+        .collect())
+}
+
+/// Elements of the given playlists, or of every playlist the user is a member of
+async fn playlist_elements(
+    conn: &mut PgConnection,
+    user_id: i32,
+    playlist_ids: &[i32],
+) -> GroovesResult<Vec<PlaylistElement>> {
+    let mut playlists: Vec<Playlist> = if playlist_ids.is_empty() {
+        sqlx::query_as(
+            r#"SELECT p.* FROM playlist p JOIN playlist_member m ON m.playlist_id = p.id
+                WHERE m.user_id = $1
+                ORDER BY p.id"#,
+        )
+        .bind(user_id)
+        .fetch_all(&mut *conn)
+        .await?
+    } else {
+        for &playlist_id in playlist_ids {
+            db::require_role(conn, playlist_id, user_id, PlaylistRole::Viewer).await?;
+        }
+
+        let mut playlists: Vec<Playlist> =
+            sqlx::query_as("SELECT * FROM playlist WHERE id = ANY($1)")
+                .bind(playlist_ids)
+                .fetch_all(&mut *conn)
+                .await?;
+        // Keep the order the playlists were given in, which is the order of the elements
+        playlists.sort_by_key(|p| playlist_ids.iter().position(|&id| id == p.id));
+        playlists
+    };
+
+    db::load_elements(conn, &mut playlists).await?;
+
+    Ok(playlists.into_iter().flat_map(|p| p.elements).collect())
+}
+
+async fn last_played(
+    db_pool: &PgPool,
+    user_id: i32,
+) -> GroovesResult<HashMap<String, DateTime<Utc>>> {
+    let plays: Vec<(String, DateTime<Utc>)> =
+        sqlx::query_as("SELECT album_id, played_at FROM album_play WHERE user_id = $1")
+            .bind(user_id)
+            .fetch_all(db_pool)
+            .await?;
+
+    Ok(plays.into_iter().collect())
+}
+
+/// Remembers when the user last played an album, for smart playlists' play history rules
+pub async fn record_album_play(
+    db_pool: &PgPool,
+    user_id: i32,
+    album_id: &str,
+) -> GroovesResult<()> {
+    sqlx::query(
+        r#"INSERT INTO album_play (user_id, album_id) VALUES ($1, $2)
+            ON CONFLICT (user_id, album_id) DO UPDATE SET played_at = now()"#,
+    )
+    .bind(user_id)
+    .bind(album_id)
+    .execute(db_pool)
+    .await?;
+
+    Ok(())
+}
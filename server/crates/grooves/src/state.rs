@@ -1,12 +1,17 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::sync::Mutex;
 
 use grooves_model::User;
 use grooves_player::manager::PlayerManager;
 use sqlx::PgPool;
 
+use crate::validation::Limits;
+
 pub struct State {
     pub db_pool: PgPool,
     pub player_manager: PlayerManager,
     pub sse_tokens: Mutex<HashMap<String, User>>,
+    /// Users that already have a task saving their resume points and album plays
+    pub play_recorders: Mutex<HashSet<i32>>,
+    pub limits: Limits,
 }
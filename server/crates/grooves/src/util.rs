@@ -1,6 +1,7 @@
 use rand::distributions::Alphanumeric;
 use rand::{thread_rng, Rng};
 
+pub mod playlist_files;
 pub mod spotify;
 
 const TOKEN_LENGTH: usize = 64;
@@ -0,0 +1,296 @@
+use std::collections::HashSet;
+use std::fmt::Write;
+
+use grooves_model::{Playlist, PlaylistElement};
+use rspotify::model::{AlbumId, TrackId};
+use rspotify::prelude::Id;
+use serde::{Deserialize, Serialize};
+
+/// Bumped whenever the grooves file format changes in a way older servers can't read
+pub const GROOVES_FILE_VERSION: u32 = 1;
+
+const GROOVES_FILE_FORMAT: &str = "grooves-playlist";
+
+/// A playlist exported from one grooves server that can be imported into another
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct GroovesFile {
+    pub format: String,
+    pub version: u32,
+    pub name: String,
+    pub elements: Vec<PlaylistElement>,
+}
+
+impl GroovesFile {
+    pub fn new(playlist: &Playlist) -> Self {
+        Self {
+            format: GROOVES_FILE_FORMAT.to_string(),
+            version: GROOVES_FILE_VERSION,
+            name: playlist.name.clone(),
+            elements: playlist.elements.clone(),
+        }
+    }
+
+    /// Whether this server knows how to read the file
+    pub fn is_supported(&self) -> bool {
+        self.format == GROOVES_FILE_FORMAT && self.version <= GROOVES_FILE_VERSION
+    }
+}
+
+/// Every song of the playlist as an XSPF track, with the element as its album
+pub fn to_xspf(playlist: &Playlist) -> String {
+    let mut xspf = String::from(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+        <playlist version=\"1\" xmlns=\"http://xspf.org/ns/0/\">\n",
+    );
+    let _ = writeln!(xspf, "  <title>{}</title>", escape_xml(&playlist.name));
+    xspf.push_str("  <trackList>\n");
+
+    for element in &playlist.elements {
+        for song in &element.songs {
+            xspf.push_str("    <track>\n");
+            let _ = writeln!(xspf, "      <location>{}</location>", song.spotify_id.uri());
+            let _ = writeln!(xspf, "      <title>{}</title>", escape_xml(&song.name));
+            let _ = writeln!(
+                xspf,
+                "      <creator>{}</creator>",
+                escape_xml(&song.artists)
+            );
+            let _ = writeln!(xspf, "      <album>{}</album>", escape_xml(&element.name));
+            if !song.image_url.is_empty() {
+                let _ = writeln!(xspf, "      <image>{}</image>", escape_xml(&song.image_url));
+            }
+            xspf.push_str("    </track>\n");
+        }
+    }
+
+    xspf.push_str("  </trackList>\n</playlist>\n");
+    xspf
+}
+
+/// Every song of the playlist as its `spotify:track:` uri
+pub fn to_m3u(playlist: &Playlist) -> String {
+    let mut m3u = String::from("#EXTM3U\n");
+    let _ = writeln!(m3u, "#PLAYLIST:{}", single_line(&playlist.name));
+
+    for song in playlist.elements.iter().flat_map(|e| &e.songs) {
+        // Extended m3u durations are in whole seconds, and -1 means unknown
+        let seconds = song.duration_ms.map_or(-1, |ms| ms / 1000);
+        let _ = writeln!(
+            m3u,
+            "#EXTINF:{seconds},{} - {}",
+            single_line(&song.artists),
+            single_line(&song.name)
+        );
+        let _ = writeln!(m3u, "{}", song.spotify_id.uri());
+    }
+
+    m3u
+}
+
+/// An element from spotify's current version of an imported element's album, with what the
+/// imported element's editors chose: its annotations and which of the album's songs are excluded
+pub fn with_editor_choices(fresh: PlaylistElement, imported: &PlaylistElement) -> PlaylistElement {
+    let excluded: HashSet<&TrackId> = imported
+        .songs
+        .iter()
+        .filter(|s| s.excluded)
+        .map(|s| &s.spotify_id)
+        .collect();
+
+    let mut element = PlaylistElement {
+        tags: imported.tags.clone(),
+        note: imported.note.clone(),
+        rating: imported.rating,
+        shuffle_weight: imported.shuffle_weight,
+        ..fresh
+    };
+    for song in &mut element.songs {
+        song.excluded = excluded.contains(&song.spotify_id);
+    }
+    include_all_if_excluded(&mut element);
+
+    element
+}
+
+/// An imported element that isn't from an album spotify has. Whether its songs are
+/// available is up to spotify, so that's left for the next refresh to find out
+pub fn without_spotify_state(mut element: PlaylistElement) -> PlaylistElement {
+    for song in &mut element.songs {
+        song.unavailable = false;
+    }
+    element.duration_ms = Some(element.songs.iter().filter_map(|s| s.duration_ms).sum());
+    include_all_if_excluded(&mut element);
+
+    element
+}
+
+/// An element with every song excluded would be skipped by the player
+fn include_all_if_excluded(element: &mut PlaylistElement) {
+    if element.songs.iter().all(|s| s.excluded) {
+        for song in &mut element.songs {
+            song.excluded = false;
+        }
+    }
+}
+
+/// A line of an album csv that isn't an album
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct InvalidEntry {
+    /// Starts at 1
+    pub line: usize,
+    pub entry: String,
+}
+
+/// Reads album ids from the first column of a csv. Each can be an id, a `spotify:album:` uri
+/// or an open.spotify.com url. Empty lines and lines starting with `#` are skipped
+pub fn parse_album_csv(csv: &str) -> (Vec<(usize, AlbumId<'static>)>, Vec<InvalidEntry>) {
+    let mut album_ids = Vec::new();
+    let mut invalid = Vec::new();
+
+    for (i, line) in csv.lines().enumerate() {
+        let line_number = i + 1;
+        let entry = line
+            .split(',')
+            .next()
+            .unwrap_or_default()
+            .trim()
+            .trim_matches('"')
+            .trim();
+
+        if entry.is_empty() || entry.starts_with('#') {
+            continue;
+        }
+
+        match parse_album_id(entry) {
+            Some(album_id) => album_ids.push((line_number, album_id)),
+            None => invalid.push(InvalidEntry {
+                line: line_number,
+                entry: entry.to_string(),
+            }),
+        }
+    }
+
+    (album_ids, invalid)
+}
+
+fn parse_album_id(entry: &str) -> Option<AlbumId<'static>> {
+    // e.g. https://open.spotify.com/album/<id>?si=<tracking id>
+    let id = match entry.split_once("/album/") {
+        Some((_, rest)) => rest.split(['?', '/', '#']).next()?,
+        None => entry,
+    };
+
+    AlbumId::from_id_or_uri(id).ok().map(AlbumId::into_static)
+}
+
+fn escape_xml(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
+
+/// M3U is line based, so names can't span lines
+fn single_line(s: &str) -> String {
+    s.replace(['\r', '\n'], " ")
+}
+
+#[cfg(test)]
+mod tests {
+    use grooves_model::Song;
+
+    use super::*;
+
+    const ALBUM_ID: &str = "4aawyAB9vmqN3uQ7FjRGTy";
+
+    #[test]
+    fn album_csv_accepts_ids_uris_and_urls() {
+        let csv = format!(
+            "# albums\n{ALBUM_ID}\n\nspotify:album:{ALBUM_ID},extra\n\
+            \"https://open.spotify.com/album/{ALBUM_ID}?si=abc\"\nnot an album\n"
+        );
+
+        let (album_ids, invalid) = parse_album_csv(&csv);
+
+        let lines: Vec<usize> = album_ids.iter().map(|(line, _)| *line).collect();
+        assert_eq!(lines, [2, 4, 5]);
+        assert!(album_ids.iter().all(|(_, id)| id.id() == ALBUM_ID));
+        assert_eq!(
+            invalid,
+            [InvalidEntry {
+                line: 6,
+                entry: "not an album".to_string()
+            }]
+        );
+    }
+
+    fn song(id: &str, excluded: bool) -> Song {
+        Song {
+            artists: "artist".to_string(),
+            duration_ms: Some(1000),
+            excluded,
+            ..Song::test(id)
+        }
+    }
+
+    #[test]
+    fn imported_elements_keep_only_editor_choices() {
+        let imported = PlaylistElement {
+            name: "edited".to_string(),
+            songs: vec![
+                Song {
+                    unavailable: true,
+                    ..song("a", true)
+                },
+                song("gone", true),
+            ],
+            rating: Some(4),
+            ..Default::default()
+        };
+        let fresh = PlaylistElement {
+            name: "album".to_string(),
+            songs: vec![song("a", false), song("b", false)],
+            ..Default::default()
+        };
+
+        let element = with_editor_choices(fresh, &imported);
+        assert_eq!(element.name, "album");
+        assert_eq!(element.rating, Some(4));
+        let excluded: Vec<bool> = element.songs.iter().map(|s| s.excluded).collect();
+        assert_eq!(excluded, [true, false]);
+
+        let element = without_spotify_state(imported);
+        assert!(element.songs.iter().all(|s| !s.unavailable && !s.excluded));
+        assert_eq!(element.duration_ms, Some(2000));
+    }
+
+    #[test]
+    fn m3u_has_durations_in_seconds() {
+        let mut element = PlaylistElement::default();
+        element.songs.push(Song {
+            duration_ms: Some(201_500),
+            ..song("song", false)
+        });
+        element.songs.push(Song {
+            duration_ms: None,
+            ..song("unknown", false)
+        });
+        let playlist = Playlist::ephemeral(1, vec![element]);
+
+        let m3u = to_m3u(&playlist);
+        assert!(m3u.contains("#EXTINF:201,artist - song\n"));
+        assert!(m3u.contains("#EXTINF:-1,artist - unknown\n"));
+    }
+
+    #[test]
+    fn xspf_escapes_names() {
+        let playlist = Playlist::ephemeral(1, Vec::new());
+        let playlist = Playlist {
+            name: "Rock & <Roll>".to_string(),
+            ..playlist
+        };
+
+        assert!(to_xspf(&playlist).contains("<title>Rock &amp; &lt;Roll&gt;</title>"));
+    }
+}
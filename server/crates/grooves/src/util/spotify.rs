@@ -1,7 +1,15 @@
+use std::collections::HashMap;
 use std::sync::Arc;
 
+use grooves_model::PlaylistElement;
+use rspotify::http::HttpError;
+use rspotify::model::{AlbumId, FullTrack, TrackId};
+use rspotify::prelude::{BaseClient, OAuthClient};
 use rspotify::sync::Mutex;
-use rspotify::{scopes, AuthCodeSpotify, Config, Credentials, OAuth, Token};
+use rspotify::{scopes, AuthCodeSpotify, ClientError, Config, Credentials, OAuth, Token};
+use sqlx::PgPool;
+
+use crate::error::GroovesResult;
 
 /// This will return a spotify client without any token
 pub fn init_client() -> AuthCodeSpotify {
@@ -12,7 +20,10 @@ pub fn init_client() -> AuthCodeSpotify {
         "user-modify-playback-state",
         "user-read-playback-state",
         "playlist-read-private",
-        "user-read-private"
+        "user-read-private",
+        "user-library-read",
+        "playlist-modify-public",
+        "playlist-modify-private"
     );
 
     let oauth = OAuth {
@@ -36,7 +47,10 @@ pub fn client_with_token(mut token: Token) -> AuthCodeSpotify {
         "user-modify-playback-state",
         "user-read-playback-state",
         "playlist-read-private",
-        "user-read-private"
+        "user-read-private",
+        "user-library-read",
+        "playlist-modify-public",
+        "playlist-modify-private"
     );
 
     let oauth = OAuth {
@@ -55,3 +69,135 @@ pub fn client_with_token(mut token: Token) -> AuthCodeSpotify {
     client.token = Arc::new(Mutex::new(Some(token)));
     client
 }
+
+/// Stores the client's token for the user if it was refreshed since `old_token`
+pub async fn save_refreshed_token(
+    client: &AuthCodeSpotify,
+    old_token: Token,
+    user_id: i32,
+    db_pool: &PgPool,
+) -> GroovesResult<()> {
+    let new_token = client.get_token().lock().await.unwrap().clone();
+    if new_token != Some(old_token) {
+        sqlx::query(r#"UPDATE "user" SET token = $1 WHERE id = $2"#)
+            .bind(sqlx::types::Json(new_token))
+            .bind(user_id)
+            .execute(db_pool)
+            .await?;
+    }
+
+    Ok(())
+}
+
+/// The most albums spotify will return from a single request
+const ALBUMS_PER_REQUEST: usize = 20;
+
+/// Fetches albums and converts them to elements, keeping the order of `album_ids`
+pub async fn fetch_album_elements(
+    client: &AuthCodeSpotify,
+    album_ids: &[AlbumId<'static>],
+) -> GroovesResult<Vec<PlaylistElement>> {
+    let mut elements = Vec::with_capacity(album_ids.len());
+
+    for chunk in album_ids.chunks(ALBUMS_PER_REQUEST) {
+        let albums = client.albums(chunk.iter().cloned(), None).await?;
+        elements.extend(albums.into_iter().map(PlaylistElement::from));
+    }
+
+    fill_isrcs(client, &mut elements).await?;
+
+    Ok(elements)
+}
+
+/// The most saved albums spotify will return from a single request
+pub const SAVED_ALBUMS_PER_REQUEST: u32 = 50;
+
+/// Converts every album in the user's library to an element, most recently saved first.
+/// This is done every time a library smart playlist is played, so ISRCs aren't filled in
+pub async fn fetch_saved_album_elements(
+    client: &AuthCodeSpotify,
+) -> GroovesResult<Vec<PlaylistElement>> {
+    let mut elements = Vec::new();
+    let mut offset = 0;
+
+    loop {
+        let page = client
+            .current_user_saved_albums_manual(None, Some(SAVED_ALBUMS_PER_REQUEST), Some(offset))
+            .await?;
+        elements.extend(
+            page.items
+                .into_iter()
+                .map(|s| PlaylistElement::from(s.album)),
+        );
+
+        if page.next.is_none() {
+            break;
+        }
+        offset += SAVED_ALBUMS_PER_REQUEST;
+    }
+
+    Ok(elements)
+}
+
+/// The most tracks spotify will return from a single request
+const TRACKS_PER_REQUEST: usize = 50;
+
+pub async fn fetch_tracks(
+    client: &AuthCodeSpotify,
+    track_ids: &[TrackId<'static>],
+) -> GroovesResult<Vec<FullTrack>> {
+    let mut tracks = Vec::with_capacity(track_ids.len());
+
+    for chunk in track_ids.chunks(TRACKS_PER_REQUEST) {
+        tracks.extend(client.tracks(chunk.iter().cloned(), None).await?);
+    }
+
+    Ok(tracks)
+}
+
+/// Albums don't include their tracks' ISRCs, so they're looked up from the full tracks
+pub async fn fill_isrcs(
+    client: &AuthCodeSpotify,
+    elements: &mut [PlaylistElement],
+) -> GroovesResult<()> {
+    let track_ids: Vec<TrackId<'static>> = elements
+        .iter()
+        .flat_map(|e| &e.songs)
+        .filter(|s| s.isrc.is_none())
+        .map(|s| s.spotify_id.clone())
+        .collect();
+
+    let isrcs: HashMap<TrackId<'static>, String> = fetch_tracks(client, &track_ids)
+        .await?
+        .into_iter()
+        .filter_map(|mut t| Some((t.id?, t.external_ids.remove("isrc")?)))
+        .collect();
+
+    for song in elements.iter_mut().flat_map(|e| &mut e.songs) {
+        if song.isrc.is_none() {
+            // The same track can be in more than one element
+            song.isrc = isrcs.get(&song.spotify_id).cloned();
+        }
+    }
+
+    Ok(())
+}
+
+pub fn get_min_image_url(images: &[rspotify::model::Image]) -> Option<&str> {
+    images
+        .iter()
+        .min_by_key(|a| a.height.unwrap_or(0))
+        .map(|img| &*img.url)
+}
+
+/// Whether spotify answered that the thing asked for doesn't exist, as opposed to the request
+/// failing for some other reason, like being rate limited
+pub fn is_not_found(error: &ClientError) -> bool {
+    match error {
+        ClientError::Http(error) => match &**error {
+            HttpError::StatusCode(response) => response.status() == 404,
+            HttpError::Client(_) => false,
+        },
+        _ => false,
+    }
+}
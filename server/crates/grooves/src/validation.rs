@@ -0,0 +1,453 @@
+use std::collections::HashMap;
+use std::error::Error;
+use std::str::FromStr;
+
+use axum::async_trait;
+use axum::extract::rejection::JsonRejection;
+use axum::extract::{FromRequest, Request};
+use axum::Json;
+use grooves_model::{ElementAnnotations, PlaylistElement, SmartRule, SmartRules};
+use rspotify::model::AlbumId;
+use serde::de::DeserializeOwned;
+
+use crate::error::{FieldError, GroovesError, GroovesResult};
+
+/// The highest shuffle weight, so one element can't crowd out every other
+pub const MAX_SHUFFLE_WEIGHT: i32 = 10;
+
+/// How big the playlists users save can be. Each limit can be set with an environment variable
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Limits {
+    /// GROOVES_MAX_NAME_LENGTH, in characters
+    pub max_name_length: usize,
+    /// GROOVES_MAX_ELEMENTS, per playlist
+    pub max_elements: usize,
+    /// GROOVES_MAX_SONGS, per element
+    pub max_songs: usize,
+    /// GROOVES_MAX_TAGS, per element
+    pub max_tags: usize,
+    /// GROOVES_MAX_TAG_LENGTH, in characters
+    pub max_tag_length: usize,
+    /// GROOVES_MAX_NOTE_LENGTH, in characters
+    pub max_note_length: usize,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Self {
+            max_name_length: 200,
+            max_elements: 2000,
+            max_songs: 500,
+            max_tags: 20,
+            max_tag_length: 50,
+            max_note_length: 2000,
+        }
+    }
+}
+
+impl Limits {
+    pub fn from_env() -> Self {
+        let defaults = Self::default();
+
+        Self {
+            max_name_length: env_or("GROOVES_MAX_NAME_LENGTH", defaults.max_name_length),
+            max_elements: env_or("GROOVES_MAX_ELEMENTS", defaults.max_elements),
+            max_songs: env_or("GROOVES_MAX_SONGS", defaults.max_songs),
+            max_tags: env_or("GROOVES_MAX_TAGS", defaults.max_tags),
+            max_tag_length: env_or("GROOVES_MAX_TAG_LENGTH", defaults.max_tag_length),
+            max_note_length: env_or("GROOVES_MAX_NOTE_LENGTH", defaults.max_note_length),
+        }
+    }
+}
+
+fn env_or<T: FromStr>(name: &str, default: T) -> T {
+    match std::env::var(name) {
+        Ok(value) => value.parse().unwrap_or_else(|_| panic!("Invalid {name}")),
+        Err(_) => default,
+    }
+}
+
+/// Collects everything wrong with a request body, so it can all be reported at once
+#[derive(Debug, Default)]
+pub struct Validator {
+    errors: Vec<FieldError>,
+}
+
+impl Validator {
+    pub fn error(&mut self, field: impl Into<String>, message: impl Into<String>) {
+        self.errors.push(FieldError {
+            field: field.into(),
+            message: message.into(),
+        });
+    }
+
+    pub fn name(&mut self, field: &str, name: &str, limits: &Limits) {
+        if name.trim().is_empty() {
+            self.error(field, "can't be empty");
+        } else if name.chars().count() > limits.max_name_length {
+            self.error(
+                field,
+                format!("can't be longer than {} characters", limits.max_name_length),
+            );
+        }
+    }
+
+    /// Checks elements that will be in a playlist alongside `existing` other elements, whose
+    /// albums are `existing_album_ids`
+    pub fn elements(
+        &mut self,
+        field: &str,
+        elements: &[PlaylistElement],
+        existing: usize,
+        existing_album_ids: &[AlbumId<'_>],
+        limits: &Limits,
+    ) {
+        if existing + elements.len() > limits.max_elements {
+            self.error(
+                field,
+                format!(
+                    "a playlist can't have more than {} elements",
+                    limits.max_elements
+                ),
+            );
+        }
+
+        let mut album_positions: HashMap<&AlbumId, Option<usize>> =
+            existing_album_ids.iter().map(|id| (id, None)).collect();
+
+        for (i, element) in elements.iter().enumerate() {
+            let field = format!("{field}[{i}]");
+
+            self.name(&format!("{field}.name"), &element.name, limits);
+
+            if element.songs.is_empty() {
+                self.error(format!("{field}.songs"), "can't be empty");
+            } else if element.songs.len() > limits.max_songs {
+                self.error(
+                    format!("{field}.songs"),
+                    format!("an element can't have more than {} songs", limits.max_songs),
+                );
+            }
+
+            self.annotation_fields(
+                &field,
+                &element.tags,
+                element.note.as_deref(),
+                element.rating,
+                element.shuffle_weight,
+                limits,
+            );
+
+            if let Some(album_id) = &element.album_id {
+                match album_positions.get(album_id) {
+                    Some(Some(first)) => self.error(
+                        format!("{field}.album_id"),
+                        format!("is the same album as [{first}]"),
+                    ),
+                    Some(None) => {
+                        self.error(format!("{field}.album_id"), "is already in the playlist")
+                    }
+                    None => {
+                        album_positions.insert(album_id, Some(i));
+                    }
+                }
+            }
+        }
+    }
+
+    /// `field` is the prefix of the annotations' fields, e.g. `elements[0]`, or empty if they're
+    /// at the top level of the body
+    pub fn annotations(&mut self, field: &str, annotations: &ElementAnnotations, limits: &Limits) {
+        self.annotation_fields(
+            field,
+            &annotations.tags,
+            annotations.note.as_deref(),
+            annotations.rating,
+            annotations.shuffle_weight,
+            limits,
+        );
+    }
+
+    fn annotation_fields(
+        &mut self,
+        field: &str,
+        tags: &[String],
+        note: Option<&str>,
+        rating: Option<i32>,
+        shuffle_weight: Option<i32>,
+        limits: &Limits,
+    ) {
+        let field = |name: &str| {
+            if field.is_empty() {
+                name.to_owned()
+            } else {
+                format!("{field}.{name}")
+            }
+        };
+
+        if tags.len() > limits.max_tags {
+            self.error(
+                field("tags"),
+                format!("can't have more than {} tags", limits.max_tags),
+            );
+        }
+        for (i, tag) in tags.iter().enumerate() {
+            if tag.chars().count() > limits.max_tag_length {
+                self.error(
+                    field(&format!("tags[{i}]")),
+                    format!("can't be longer than {} characters", limits.max_tag_length),
+                );
+            }
+        }
+        if note.is_some_and(|n| n.chars().count() > limits.max_note_length) {
+            self.error(
+                field("note"),
+                format!("can't be longer than {} characters", limits.max_note_length),
+            );
+        }
+
+        if rating.is_some_and(|r| !(1..=5).contains(&r)) {
+            self.error(field("rating"), "must be from 1 to 5");
+        }
+        if shuffle_weight.is_some_and(|w| !(1..=MAX_SHUFFLE_WEIGHT).contains(&w)) {
+            self.error(
+                field("shuffle_weight"),
+                format!("must be from 1 to {MAX_SHUFFLE_WEIGHT}"),
+            );
+        }
+    }
+
+    /// `field` is where the rules are in the body, or empty if they're the whole body
+    pub fn smart_rules(&mut self, field: &str, rules: &SmartRules) {
+        let field = |name: &str| {
+            if field.is_empty() {
+                name.to_owned()
+            } else {
+                format!("{field}.{name}")
+            }
+        };
+
+        if rules.limit == Some(0) {
+            self.error(field("limit"), "must be more than 0");
+        }
+
+        for (i, rule) in rules.rules.iter().enumerate() {
+            match rule {
+                SmartRule::MinRating { rating } if !(1..=5).contains(rating) => {
+                    self.error(field(&format!("rules[{i}].rating")), "must be from 1 to 5")
+                }
+                SmartRule::NotPlayedFor { days } if *days < 0 => {
+                    self.error(field(&format!("rules[{i}].days")), "can't be negative")
+                }
+                _ => {}
+            }
+        }
+    }
+
+    pub fn finish(self) -> GroovesResult<()> {
+        if self.errors.is_empty() {
+            Ok(())
+        } else {
+            Err(GroovesError::Validation(self.errors))
+        }
+    }
+}
+
+/// Checks a whole playlist that's about to be saved
+pub fn validate_playlist(
+    name: &str,
+    elements: &[PlaylistElement],
+    limits: &Limits,
+) -> GroovesResult<()> {
+    let mut validator = Validator::default();
+    validator.name("name", name, limits);
+    validator.elements("elements", elements, 0, &[], limits);
+    validator.finish()
+}
+
+/// Like `Json`, but bodies that don't deserialize are a validation error for the field that
+/// was wrong instead of a plain text rejection
+pub struct JsonBody<T>(pub T);
+
+#[async_trait]
+impl<T, S> FromRequest<S> for JsonBody<T>
+where
+    T: DeserializeOwned,
+    S: Send + Sync,
+{
+    type Rejection = GroovesError;
+
+    async fn from_request(req: Request, state: &S) -> Result<Self, Self::Rejection> {
+        match Json::<T>::from_request(req, state).await {
+            Ok(Json(value)) => Ok(Self(value)),
+            Err(rejection) => Err(rejection_error(rejection)),
+        }
+    }
+}
+
+fn rejection_error(rejection: JsonRejection) -> GroovesError {
+    let JsonRejection::JsonDataError(_) = rejection else {
+        return GroovesError::InvalidRequest;
+    };
+
+    let mut source = rejection.source();
+    while let Some(error) = source {
+        if let Some(error) = error.downcast_ref::<serde_path_to_error::Error<serde_json::Error>>() {
+            return GroovesError::Validation(vec![FieldError {
+                field: error.path().to_string(),
+                message: error.inner().to_string(),
+            }]);
+        }
+        source = error.source();
+    }
+
+    GroovesError::InvalidRequest
+}
+
+#[cfg(test)]
+mod tests {
+    use grooves_model::Song;
+
+    use super::*;
+
+    fn element(name: &str, album_id: &str) -> PlaylistElement {
+        PlaylistElement {
+            name: name.to_owned(),
+            album_id: Some(AlbumId::from_id(album_id.to_owned()).unwrap()),
+            songs: vec![Song::test("6rqhFgbbKwnb9MLmUQDhG6")],
+            ..Default::default()
+        }
+    }
+
+    fn fields(result: GroovesResult<()>) -> Vec<String> {
+        match result {
+            Err(GroovesError::Validation(errors)) => errors.into_iter().map(|e| e.field).collect(),
+            other => panic!("expected validation errors, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn every_invalid_field_is_reported() {
+        let mut empty = element(" ", "1weenld61qoidwYuZ1GESA");
+        empty.songs.clear();
+
+        let elements = [
+            element("a", "4LH4d3cOWNNsVw41Gqt2kv"),
+            empty,
+            element("c", "4LH4d3cOWNNsVw41Gqt2kv"),
+        ];
+
+        assert_eq!(
+            fields(validate_playlist("", &elements, &Limits::default())),
+            vec![
+                "name",
+                "elements[1].name",
+                "elements[1].songs",
+                "elements[2].album_id"
+            ]
+        );
+    }
+
+    #[test]
+    fn limits_are_enforced() {
+        let limits = Limits {
+            max_name_length: 3,
+            max_elements: 1,
+            max_songs: 5,
+            ..Default::default()
+        };
+        let elements = [
+            element("a", "4LH4d3cOWNNsVw41Gqt2kv"),
+            element("b", "1weenld61qoidwYuZ1GESA"),
+        ];
+
+        assert_eq!(
+            fields(validate_playlist("long", &elements, &limits)),
+            vec!["name", "elements"]
+        );
+        assert!(validate_playlist("ok", &elements[..1], &limits).is_ok());
+    }
+
+    #[test]
+    fn annotations_are_checked() {
+        let mut rated = element("a", "4LH4d3cOWNNsVw41Gqt2kv");
+        rated.rating = Some(7);
+        rated.shuffle_weight = Some(MAX_SHUFFLE_WEIGHT + 1);
+
+        assert_eq!(
+            fields(validate_playlist("ok", &[rated], &Limits::default())),
+            vec!["elements[0].rating", "elements[0].shuffle_weight"]
+        );
+
+        let limits = Limits {
+            max_tags: 2,
+            max_tag_length: 3,
+            max_note_length: 4,
+            ..Default::default()
+        };
+        let annotations = ElementAnnotations {
+            tags: vec!["ok".to_owned(), "long".to_owned(), "ok".to_owned()],
+            note: Some("too long".to_owned()),
+            rating: Some(0),
+            shuffle_weight: Some(1),
+        };
+
+        let mut validator = Validator::default();
+        validator.annotations("", &annotations, &limits);
+        assert_eq!(
+            fields(validator.finish()),
+            vec!["tags", "tags[1]", "note", "rating"]
+        );
+    }
+
+    #[test]
+    fn smart_rules_are_checked() {
+        let rules = SmartRules {
+            source: grooves_model::SmartSource::Library,
+            rules: vec![
+                SmartRule::MinRating { rating: 6 },
+                SmartRule::Tag {
+                    tag: "a".to_owned(),
+                },
+                SmartRule::NotPlayedFor { days: -1 },
+            ],
+            limit: Some(0),
+        };
+
+        let mut validator = Validator::default();
+        validator.smart_rules("rules", &rules);
+        assert_eq!(
+            fields(validator.finish()),
+            vec![
+                "rules.limit",
+                "rules.rules[0].rating",
+                "rules.rules[2].days"
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn malformed_fields_are_reported_with_their_path() {
+        #[derive(serde::Deserialize)]
+        struct Payload {
+            #[allow(dead_code)]
+            elements: Vec<PlaylistElement>,
+        }
+
+        let body = r#"{"elements": [{"name": "a", "image_url": "", "artists": "", "songs": [
+            {"name": "b", "image_url": "", "artists": "", "spotify_id": "not an id!"}
+        ]}]}"#;
+        let req = Request::builder()
+            .header(axum::http::header::CONTENT_TYPE, "application/json")
+            .body(axum::body::Body::from(body))
+            .unwrap();
+
+        match JsonBody::<Payload>::from_request(req, &()).await {
+            Err(GroovesError::Validation(errors)) => {
+                assert_eq!(errors.len(), 1);
+                assert_eq!(errors[0].field, "elements[0].songs[0].spotify_id");
+            }
+            _ => panic!("expected a validation error"),
+        }
+    }
+}
@@ -0,0 +1,9 @@
+CREATE TABLE IF NOT EXISTS resume_point(
+    user_id INT NOT NULL REFERENCES "user"(id),
+    playlist_id INT NOT NULL REFERENCES playlist(id) ON DELETE CASCADE,
+    element_order JSONB NOT NULL,
+    current_element INT NOT NULL,
+    current_song INT NOT NULL,
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    PRIMARY KEY (user_id, playlist_id)
+);
@@ -0,0 +1 @@
+ALTER TABLE resume_point ADD COLUMN IF NOT EXISTS seed BIGINT;
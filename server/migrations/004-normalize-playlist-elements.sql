@@ -0,0 +1,45 @@
+CREATE TABLE IF NOT EXISTS playlist_element(
+    id SERIAL PRIMARY KEY NOT NULL,
+    playlist_id INT NOT NULL REFERENCES playlist(id) ON DELETE CASCADE,
+    position INT NOT NULL,
+    name TEXT NOT NULL,
+    image_url TEXT NOT NULL,
+    artists TEXT NOT NULL,
+    UNIQUE (playlist_id, position) DEFERRABLE INITIALLY IMMEDIATE
+);
+
+CREATE TABLE IF NOT EXISTS playlist_element_track(
+    element_id INT NOT NULL REFERENCES playlist_element(id) ON DELETE CASCADE,
+    position INT NOT NULL,
+    name TEXT NOT NULL,
+    image_url TEXT NOT NULL,
+    artists TEXT NOT NULL,
+    spotify_id TEXT NOT NULL,
+    PRIMARY KEY (element_id, position)
+);
+
+CREATE INDEX IF NOT EXISTS playlist_element_track_spotify_id ON playlist_element_track(spotify_id);
+
+-- Move the elements out of the JSONB column, if it hasn't been done already
+DO $$
+BEGIN
+    IF EXISTS (
+        SELECT 1 FROM information_schema.columns
+        WHERE table_name = 'playlist' AND column_name = 'elements'
+    ) THEN
+        INSERT INTO playlist_element (playlist_id, position, name, image_url, artists)
+        SELECT p.id, e.position - 1, e.element->>'name', e.element->>'image_url', e.element->>'artists'
+        FROM playlist p
+        CROSS JOIN LATERAL jsonb_array_elements(p.elements) WITH ORDINALITY AS e(element, position);
+
+        INSERT INTO playlist_element_track (element_id, position, name, image_url, artists, spotify_id)
+        SELECT pe.id, s.position - 1, s.song->>'name', s.song->>'image_url', s.song->>'artists', s.song->>'spotify_id'
+        FROM playlist p
+        CROSS JOIN LATERAL jsonb_array_elements(p.elements) WITH ORDINALITY AS e(element, position)
+        JOIN playlist_element pe ON pe.playlist_id = p.id AND pe.position = e.position - 1
+        CROSS JOIN LATERAL jsonb_array_elements(e.element->'songs') WITH ORDINALITY AS s(song, position);
+
+        ALTER TABLE playlist DROP COLUMN elements;
+    END IF;
+END
+$$;
@@ -0,0 +1,10 @@
+CREATE TABLE IF NOT EXISTS playlist_revision(
+    id SERIAL PRIMARY KEY NOT NULL,
+    playlist_id INT NOT NULL REFERENCES playlist(id) ON DELETE CASCADE,
+    author_id INT NOT NULL REFERENCES "user"(id),
+    name TEXT NOT NULL,
+    elements JSONB NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+
+CREATE INDEX IF NOT EXISTS playlist_revision_playlist_id ON playlist_revision(playlist_id);
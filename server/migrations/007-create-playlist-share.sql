@@ -0,0 +1,6 @@
+CREATE TABLE IF NOT EXISTS playlist_share(
+    id SERIAL PRIMARY KEY NOT NULL,
+    playlist_id INT NOT NULL REFERENCES playlist(id) ON DELETE CASCADE,
+    token TEXT NOT NULL UNIQUE,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
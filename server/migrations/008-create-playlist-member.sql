@@ -0,0 +1,28 @@
+DO $$
+BEGIN
+    CREATE TYPE playlist_role AS ENUM ('viewer', 'editor', 'owner');
+EXCEPTION
+    WHEN duplicate_object THEN NULL;
+END
+$$;
+
+CREATE TABLE IF NOT EXISTS playlist_member(
+    playlist_id INT NOT NULL REFERENCES playlist(id) ON DELETE CASCADE,
+    user_id INT NOT NULL REFERENCES "user"(id),
+    role playlist_role NOT NULL,
+    PRIMARY KEY (playlist_id, user_id)
+);
+
+CREATE INDEX IF NOT EXISTS playlist_member_user_id ON playlist_member(user_id);
+
+INSERT INTO playlist_member (playlist_id, user_id, role)
+SELECT id, owner_id, 'owner' FROM playlist
+ON CONFLICT DO NOTHING;
+
+CREATE TABLE IF NOT EXISTS playlist_invite(
+    id SERIAL PRIMARY KEY NOT NULL,
+    playlist_id INT NOT NULL REFERENCES playlist(id) ON DELETE CASCADE,
+    token TEXT NOT NULL UNIQUE,
+    role playlist_role NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
@@ -0,0 +1,5 @@
+CREATE TABLE IF NOT EXISTS library_sync(
+    playlist_id INT PRIMARY KEY NOT NULL REFERENCES playlist(id) ON DELETE CASCADE,
+    user_id INT NOT NULL REFERENCES "user"(id),
+    synced_at TIMESTAMPTZ NOT NULL
+);
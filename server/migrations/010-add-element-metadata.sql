@@ -0,0 +1,16 @@
+ALTER TABLE playlist_element
+    ADD COLUMN IF NOT EXISTS album_id TEXT,
+    ADD COLUMN IF NOT EXISTS release_date TEXT,
+    ADD COLUMN IF NOT EXISTS label TEXT,
+    ADD COLUMN IF NOT EXISTS genres JSONB NOT NULL DEFAULT '[]',
+    ADD COLUMN IF NOT EXISTS duration_ms INT;
+
+CREATE INDEX IF NOT EXISTS playlist_element_album_id ON playlist_element(album_id);
+
+ALTER TABLE playlist_element_track
+    ADD COLUMN IF NOT EXISTS duration_ms INT,
+    ADD COLUMN IF NOT EXISTS track_number INT,
+    ADD COLUMN IF NOT EXISTS disc_number INT,
+    ADD COLUMN IF NOT EXISTS explicit BOOLEAN NOT NULL DEFAULT FALSE,
+    ADD COLUMN IF NOT EXISTS isrc TEXT,
+    ADD COLUMN IF NOT EXISTS artist_ids JSONB NOT NULL DEFAULT '[]';
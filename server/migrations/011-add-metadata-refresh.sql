@@ -0,0 +1,4 @@
+ALTER TABLE playlist ADD COLUMN IF NOT EXISTS refreshed_at TIMESTAMPTZ;
+
+ALTER TABLE playlist_element_track
+    ADD COLUMN IF NOT EXISTS unavailable BOOLEAN NOT NULL DEFAULT FALSE;
@@ -0,0 +1,2 @@
+ALTER TABLE playlist_element_track
+    ADD COLUMN IF NOT EXISTS excluded BOOLEAN NOT NULL DEFAULT FALSE;
@@ -0,0 +1,5 @@
+ALTER TABLE playlist_element
+    ADD COLUMN IF NOT EXISTS tags JSONB NOT NULL DEFAULT '[]',
+    ADD COLUMN IF NOT EXISTS note TEXT,
+    ADD COLUMN IF NOT EXISTS rating INT CHECK (rating BETWEEN 1 AND 5),
+    ADD COLUMN IF NOT EXISTS shuffle_weight INT CHECK (shuffle_weight >= 1);
@@ -0,0 +1,11 @@
+CREATE TABLE IF NOT EXISTS smart_playlist(
+    playlist_id INT PRIMARY KEY NOT NULL REFERENCES playlist(id) ON DELETE CASCADE,
+    rules JSONB NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS album_play(
+    user_id INT NOT NULL REFERENCES "user"(id),
+    album_id TEXT NOT NULL,
+    played_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    PRIMARY KEY (user_id, album_id)
+);
@@ -0,0 +1,16 @@
+CREATE TABLE IF NOT EXISTS playlist_folder(
+    id SERIAL PRIMARY KEY NOT NULL,
+    user_id INT NOT NULL REFERENCES "user"(id),
+    parent_id INT REFERENCES playlist_folder(id),
+    name TEXT NOT NULL,
+    position INT NOT NULL DEFAULT 0
+);
+
+CREATE INDEX IF NOT EXISTS playlist_folder_user_id ON playlist_folder(user_id);
+
+-- Each member organizes the playlists they have access to for themselves
+ALTER TABLE playlist_member
+    ADD COLUMN IF NOT EXISTS folder_id INT REFERENCES playlist_folder(id) ON DELETE SET NULL,
+    ADD COLUMN IF NOT EXISTS position INT NOT NULL DEFAULT 0,
+    ADD COLUMN IF NOT EXISTS pinned BOOLEAN NOT NULL DEFAULT FALSE,
+    ADD COLUMN IF NOT EXISTS archived BOOLEAN NOT NULL DEFAULT FALSE;
@@ -0,0 +1,10 @@
+DO $$
+BEGIN
+    CREATE TYPE explicit_filter AS ENUM ('allow', 'skip_tracks', 'skip_elements');
+EXCEPTION
+    WHEN duplicate_object THEN NULL;
+END
+$$;
+
+ALTER TABLE "user"
+    ADD COLUMN IF NOT EXISTS explicit_filter explicit_filter NOT NULL DEFAULT 'allow';
@@ -0,0 +1 @@
+ALTER TABLE playlist ADD COLUMN IF NOT EXISTS refresh_failed_at TIMESTAMPTZ;
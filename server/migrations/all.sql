@@ -1 +1,17 @@
 \i 001-create-initial.sql
+\i 002-create-resume-point.sql
+\i 003-add-resume-point-seed.sql
+\i 004-normalize-playlist-elements.sql
+\i 005-create-playlist-revision.sql
+\i 006-add-playlist-version.sql
+\i 007-create-playlist-share.sql
+\i 008-create-playlist-member.sql
+\i 009-create-library-sync.sql
+\i 010-add-element-metadata.sql
+\i 011-add-metadata-refresh.sql
+\i 012-add-track-exclusion.sql
+\i 013-add-element-annotations.sql
+\i 014-create-smart-playlist.sql
+\i 015-create-playlist-folder.sql
+\i 016-add-explicit-filter.sql
+\i 017-add-refresh-failure.sql